@@ -0,0 +1,393 @@
+//! Derive macro generating the `AutoRequestable` SQL boilerplate of alterego models.
+//!
+//! Two shapes of structs are supported:
+//!
+//! * Wrappers around a TDLib type, whose columns are listed on the wrapped field:
+//!
+//! ```ignore
+//! #[derive(AutoRequestable)]
+//! #[auto_requestable(table = "MESSAGES", archive = "MESSAGES_ARCHIVE")]
+//! pub struct MessageWrapper(
+//!     #[column(id)]
+//!     #[column(sender_id, json)]
+//!     #[column(chat_id)]
+//!     // ...
+//!     Message,
+//! );
+//! ```
+//!
+//! * Plain structs, where every named field is a column and may carry a `#[column(..)]`
+//!   attribute with the same options.
+//!
+//! Column options: `rename = "..."` sets the SQL column name, `json` stores the value as
+//! serialized JSON and `skip` never writes the field and reads it back as its default.
+//! The table key defaults to the `id` column and can be changed with `id = "..."`, listing
+//! comma separated columns for a composite key. The type of the key is the type of its fields,
+//! except for wrappers whose wrapped fields can't be seen by the macro: they default to `i64`
+//! and can set another type with `id_type = "..."`, a tuple for a composite key.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{ext::IdentExt, parse_macro_input, Data, DeriveInput, Fields, Ident, LitStr, Type};
+
+struct Column {
+    field: Ident,
+    // Only known for plain structs
+    ty: Option<Type>,
+    name: String,
+    json: bool,
+    skip: bool,
+}
+
+enum Shape {
+    Wrapper(Box<Type>),
+    Named,
+}
+
+#[proc_macro_derive(AutoRequestable, attributes(auto_requestable, column))]
+pub fn derive_auto_requestable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let ident = &input.ident;
+
+    let mut table = None;
+    let mut archive = None;
+    let mut id = String::from("id");
+    let mut id_type = None;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("auto_requestable"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("table") {
+                table = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else if meta.path.is_ident("archive") {
+                archive = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else if meta.path.is_ident("id") {
+                id = meta.value()?.parse::<LitStr>()?.value();
+            } else if meta.path.is_ident("id_type") {
+                id_type = Some(meta.value()?.parse::<LitStr>()?.parse::<Type>()?);
+            } else {
+                return Err(meta.error("unsupported auto_requestable option"));
+            }
+            Ok(())
+        })?;
+    }
+    let table = table.ok_or_else(|| {
+        syn::Error::new_spanned(ident, "missing #[auto_requestable(table = \"...\")]")
+    })?;
+
+    let (shape, columns) = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                let field = &fields.unnamed[0];
+                let mut columns = Vec::new();
+                for attr in field
+                    .attrs
+                    .iter()
+                    .filter(|attr| attr.path().is_ident("column"))
+                {
+                    columns.push(parse_column(attr, None, None)?);
+                }
+                (Shape::Wrapper(Box::new(field.ty.clone())), columns)
+            }
+            Fields::Named(fields) => {
+                let mut columns = Vec::new();
+                for field in &fields.named {
+                    let field_ident = field.ident.clone().unwrap();
+                    let mut column = Column {
+                        name: field_ident.unraw().to_string(),
+                        field: field_ident.clone(),
+                        ty: Some(field.ty.clone()),
+                        json: false,
+                        skip: false,
+                    };
+                    for attr in field
+                        .attrs
+                        .iter()
+                        .filter(|attr| attr.path().is_ident("column"))
+                    {
+                        column =
+                            parse_column(attr, Some(field_ident.clone()), Some(field.ty.clone()))?;
+                    }
+                    columns.push(column);
+                }
+                if id_type.is_some() {
+                    return Err(syn::Error::new_spanned(
+                        ident,
+                        "id_type is only for wrappers, plain structs take it from their fields",
+                    ));
+                }
+                (Shape::Named, columns)
+            }
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    ident,
+                    "AutoRequestable needs named fields or a single wrapped type",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                ident,
+                "AutoRequestable can only be derived for structs",
+            ))
+        }
+    };
+
+    let ids = id.split(',').map(str::trim).collect::<Vec<_>>();
+    let id_columns = ids
+        .iter()
+        .map(|id| {
+            columns
+                .iter()
+                .find(|column| column.name == *id)
+                .ok_or_else(|| syn::Error::new_spanned(ident, format!("no column named '{id}'")))
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+    let stored = columns
+        .iter()
+        .filter(|column| !column.skip)
+        .collect::<Vec<_>>();
+
+    let access = |column: &Column| {
+        let field = &column.field;
+        match shape {
+            Shape::Wrapper(_) => quote!(self.0.#field),
+            Shape::Named => quote!(self.#field),
+        }
+    };
+    let value = |column: &Column| {
+        let access = access(column);
+        if column.json {
+            quote!(&serde_json::to_string(&#access)?)
+        } else {
+            quote!(&#access)
+        }
+    };
+    let param = |column: &Column| LitStr::new(&format!(":{}", column.name), Span::call_site());
+
+    let id_accesses = id_columns.iter().map(|column| {
+        let access = access(column);
+        quote!(::core::clone::Clone::clone(&#access))
+    });
+    let id_params = id_columns.iter().map(|column| param(column));
+    let id_types = id_columns
+        .iter()
+        .map(|column| match &column.ty {
+            Some(ty) => quote!(#ty),
+            None => quote!(i64),
+        })
+        .collect::<Vec<_>>();
+    let (default_id_type, id_access, id_values) = if id_columns.len() == 1 {
+        (
+            quote!(#(#id_types)*),
+            quote!(#(#id_accesses)*),
+            vec![quote!(id)],
+        )
+    } else {
+        let id_values = (0..id_columns.len())
+            .map(|index| {
+                let index = syn::Index::from(index);
+                quote!(id.#index)
+            })
+            .collect();
+        (
+            quote!((#(#id_types),*)),
+            quote!((#(#id_accesses),*)),
+            id_values,
+        )
+    };
+    let id_type = id_type.map_or(default_id_type, |id_type| quote!(#id_type));
+    let id_condition = ids
+        .iter()
+        .map(|id| format!("{id} = :{id}"))
+        .collect::<Vec<_>>()
+        .join(" AND ");
+
+    let fields = columns.iter().map(|column| &column.field);
+    let reads = columns.iter().map(|column| {
+        let name = &column.name;
+        if column.skip {
+            quote!(::core::default::Default::default())
+        } else if column.json {
+            quote!(crate::models::json_column(row, #name)?)
+        } else {
+            quote!(row.get(#name)?)
+        }
+    });
+    let construct = match &shape {
+        Shape::Wrapper(ty) => quote!(Self(#ty { #(#fields: #reads,)* })),
+        Shape::Named => quote!(Self { #(#fields: #reads,)* }),
+    };
+
+    let insert_params = stored.iter().map(|column| param(column));
+    let insert_values = stored.iter().map(|column| value(column));
+    let insert_params = quote!(rusqlite::named_params! { #(#insert_params: #insert_values,)* });
+    let insert_request = |table: &str| {
+        format!(
+            "INSERT INTO {table} ({}) VALUES ({})",
+            stored
+                .iter()
+                .map(|column| column.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            stored
+                .iter()
+                .map(|column| format!(":{}", column.name))
+                .collect::<Vec<_>>()
+                .join(", "),
+        )
+    };
+    let insert_request_table = insert_request(&table);
+
+    let updated = stored
+        .iter()
+        .filter(|column| !ids.contains(&column.name.as_str()))
+        .collect::<Vec<_>>();
+    // A table made of its key only has nothing to update, and is upserted by ignoring the rows
+    // already there
+    let (update_impl, upsert_impl) = if updated.is_empty() {
+        let upsert_request = format!("{insert_request_table} ON CONFLICT DO NOTHING");
+        (
+            quote! {
+                fn update(&self, _conn: &rusqlite::Connection) -> crate::error::AlterResult<()> {
+                    Ok(())
+                }
+            },
+            quote! {
+                fn upsert(&self, conn: &rusqlite::Connection) -> crate::error::AlterResult<()> {
+                    conn.execute(#upsert_request, #insert_params)?;
+                    Ok(())
+                }
+            },
+        )
+    } else {
+        let update_request = format!(
+            "UPDATE {table} SET {} WHERE {id_condition}",
+            updated
+                .iter()
+                .map(|column| format!("{0} = :{0}", column.name))
+                .collect::<Vec<_>>()
+                .join(", "),
+        );
+        let update_params = stored.iter().map(|column| param(column));
+        let update_values = stored.iter().map(|column| value(column));
+        (
+            quote! {
+                fn update(&self, conn: &rusqlite::Connection) -> crate::error::AlterResult<()> {
+                    conn.execute(
+                        #update_request,
+                        rusqlite::named_params! { #(#update_params: #update_values,)* },
+                    )?;
+                    Ok(())
+                }
+            },
+            quote!(),
+        )
+    };
+
+    let select_by_id_request =
+        format!("SELECT rowid AS row_id, * FROM {table} WHERE {id_condition}");
+
+    let archive_impl = archive.map(|archive| {
+        let archive_request = insert_request(&archive);
+        quote! {
+            impl #ident {
+                pub fn archive(&self, conn: &rusqlite::Connection) -> crate::error::AlterResult<()> {
+                    conn.execute(#archive_request, #insert_params)?;
+                    Ok(())
+                }
+            }
+        }
+    });
+
+    Ok(quote! {
+        impl crate::models::AutoRequestable for #ident {
+            type UniqueIdentifier = #id_type;
+
+            const TABLE: &'static str = #table;
+
+            fn get_id(&self) -> Self::UniqueIdentifier {
+                #id_access
+            }
+
+            fn from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
+                Ok(#construct)
+            }
+
+            fn select_by_id(
+                id: Self::UniqueIdentifier,
+                conn: &rusqlite::Connection,
+            ) -> crate::error::AlterResult<Option<Self>> {
+                let row = rusqlite::OptionalExtension::optional(
+                    conn.prepare(#select_by_id_request)?.query_row(
+                        rusqlite::named_params! { #(#id_params: #id_values,)* },
+                        |row| Ok((row.get::<_, i64>("row_id")?, Self::from_row(row))),
+                    ),
+                )?;
+                match row {
+                    Some((row_id, entity)) => Ok(Some(entity.map_err(|error| {
+                        crate::models::RowError {
+                            table: Self::TABLE,
+                            row_id,
+                            error,
+                        }
+                    })?)),
+                    None => Ok(None),
+                }
+            }
+
+            fn insert(&self, conn: &rusqlite::Connection) -> crate::error::AlterResult<()> {
+                conn.execute(#insert_request_table, #insert_params)?;
+                Ok(())
+            }
+
+            #update_impl
+
+            #upsert_impl
+        }
+
+        #archive_impl
+    })
+}
+
+fn parse_column(
+    attr: &syn::Attribute,
+    field: Option<Ident>,
+    ty: Option<Type>,
+) -> syn::Result<Column> {
+    let mut field = field;
+    let mut rename = None;
+    let mut json = false;
+    let mut skip = false;
+    attr.parse_nested_meta(|meta| {
+        if meta.path.is_ident("rename") {
+            rename = Some(meta.value()?.parse::<LitStr>()?.value());
+        } else if meta.path.is_ident("json") {
+            json = true;
+        } else if meta.path.is_ident("skip") {
+            skip = true;
+        } else if field.is_none() {
+            field = Some(meta.path.require_ident()?.clone());
+        } else {
+            return Err(meta.error("unsupported column option"));
+        }
+        Ok(())
+    })?;
+    let field = field.ok_or_else(|| syn::Error::new_spanned(attr, "missing column field"))?;
+    Ok(Column {
+        name: rename.unwrap_or_else(|| field.unraw().to_string()),
+        field,
+        ty,
+        json,
+        skip,
+    })
+}
@@ -4,77 +4,422 @@ use std::{
     time,
 };
 
-use log::{debug, error, info};
+use serde::Serialize;
+
 use tdlib::{
-    enums::{InputMessageContent, MessageReplyTo, MessageSender, User},
+    enums::{ChatAction, InputMessageContent, MessageReplyTo, MessageSender, Update, User},
     functions,
-    types::{FormattedText, InputMessageText, Message, MessageReplyToMessage, MessageSenderUser},
+    types::{
+        FormattedText, InputMessageText, Message, MessageReplyToMessage, MessageSenderUser,
+        UpdateChatAction, UpdateNewMessage,
+    },
 };
-use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::sync::{broadcast, oneshot, watch};
+use tracing::{debug, error, field, info, info_span, Instrument, Span};
 
 use crate::{
-    database::Database, error::AlterResult, models::message_wrapper::MessageWrapper, ollama, utils,
+    commands,
+    config::{AccountConfig, AiConfig, Config},
+    database::Database,
+    error::AlterResult,
+    exemplars, metrics,
+    models::chat_reply_mode::{ChatReplyMode, ReplyMode},
+    models::inference_audit::{InferenceAudit, Outcome},
+    models::message_provenance::{Approval, MessageProvenance},
+    models::message_wrapper::MessageWrapper,
+    ollama, queue, utils,
 };
 
-const READING_WPM_MIN: f64 = 180.;
-const READING_WPM_MAX: f64 = 250.;
-const THINKING_WPM_MIN: f64 = 1000.;
-const THINKING_WPM_MAX: f64 = 3000.;
-const TYPING_WPM_MIN: f64 = 80.;
-const TYPING_WPM_MAX: f64 = 180.;
+// Asks a thought to stop, for the given reason, and acknowledges once it has
+type Interrupt = oneshot::Sender<(&'static str, oneshot::Sender<()>)>;
+
+const EVENTS_CAPACITY: usize = 256;
+// Long enough for any message to be sent or to fail
+const SENDING_EXPIRY: time::Duration = time::Duration::from_secs(10 * 60);
+
+/// The answers being written for an account and the chats I took over, shared with the
+/// dashboard.
+pub struct Control {
+    session: Mutex<Option<Session>>,
+    thoughts: Mutex<HashMap<i64, Thought>>,
+    // Chats I took over, until when
+    taken_over: Mutex<HashMap<i64, time::Instant>>,
+    // Messages being sent, by chat and temporary id
+    sending: Mutex<HashMap<(i64, i64), Sending>>,
+    events_tx: broadcast::Sender<Event>,
+}
+
+// Whichever of its provenance or its final id comes first waits for the other
+enum Sending {
+    Provenance(MessageProvenance, time::Instant),
+    Sent(i64, time::Instant),
+}
+
+/// The Telegram client of an account, once logged in.
+#[derive(Debug, Clone, Copy)]
+pub struct Session {
+    pub client_id: i32,
+    pub me_id: i64,
+}
+
+/// What happens in the chats of an account, as streamed by the API.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    Message {
+        chat_id: i64,
+        message_id: i64,
+        is_outgoing: bool,
+        date: i64,
+        text: Option<String>,
+    },
+    ThoughtStarted {
+        chat_id: i64,
+        message_id: i64,
+    },
+    ThoughtFinished {
+        chat_id: i64,
+        message_id: i64,
+        outcome: &'static str,
+    },
+    TakenOver {
+        chat_id: i64,
+    },
+    ReplyMode {
+        chat_id: i64,
+        mode: &'static str,
+    },
+}
+
+struct Thought {
+    message_id: i64,
+    started_at: i64,
+    interrupt_tx: Interrupt,
+}
+
+/// An answer being written
+#[derive(Debug, Clone, Serialize)]
+pub struct ThoughtState {
+    pub chat_id: i64,
+    /// The message being answered
+    pub message_id: i64,
+    pub started_at: i64,
+}
+
+impl Default for Control {
+    fn default() -> Self {
+        Self {
+            session: Mutex::default(),
+            thoughts: Mutex::default(),
+            taken_over: Mutex::default(),
+            sending: Mutex::default(),
+            events_tx: broadcast::channel(EVENTS_CAPACITY).0,
+        }
+    }
+}
+
+impl Control {
+    pub fn session(&self) -> Option<Session> {
+        *self.session.lock().unwrap()
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.events_tx.subscribe()
+    }
+
+    pub fn publish(&self, event: Event) {
+        // Nobody may be listening
+        let _ = self.events_tx.send(event);
+    }
+
+    pub fn thoughts(&self) -> Vec<ThoughtState> {
+        let mut thoughts = self
+            .thoughts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(chat_id, thought)| ThoughtState {
+                chat_id: *chat_id,
+                message_id: thought.message_id,
+                started_at: thought.started_at,
+            })
+            .collect::<Vec<_>>();
+        thoughts.sort_by_key(|thought| thought.started_at);
+        thoughts
+    }
+
+    /// How long the chat stays taken over, if it is.
+    pub fn taken_over_for(&self, chat_id: i64) -> Option<time::Duration> {
+        let until = *self.taken_over.lock().unwrap().get(&chat_id)?;
+        until
+            .checked_duration_since(time::Instant::now())
+            .filter(|left| !left.is_zero())
+    }
+
+    /// Lets alterego answer again in a chat I took over.
+    pub fn resume(&self, chat_id: i64) {
+        self.taken_over.lock().unwrap().remove(&chat_id);
+    }
+
+    /// Stops writing the answer of a chat, and waits until it is stopped.
+    pub async fn interrupt(&self, chat_id: i64, reason: &'static str) {
+        let thought = self.thoughts.lock().unwrap().remove(&chat_id);
+        if let Some(Thought { interrupt_tx, .. }) = thought {
+            if !interrupt_tx.is_closed() {
+                let (interrupt_ack_tx, interrupt_ack_rx) = tokio::sync::oneshot::channel();
+                if interrupt_tx.send((reason, interrupt_ack_tx)).is_ok() {
+                    let _ = interrupt_ack_rx.await;
+                }
+            }
+        }
+    }
+
+    // I am answering myself from another device, stand down for a while
+    async fn take_over(&self, chat_id: i64, takeover_pause: time::Duration) {
+        let previous = self
+            .taken_over
+            .lock()
+            .unwrap()
+            .insert(chat_id, time::Instant::now() + takeover_pause);
+        if previous.is_none() {
+            info!(chat_id, "Taken over, pausing for {takeover_pause:?}");
+            self.publish(Event::TakenOver { chat_id });
+        }
+        self.interrupt(chat_id, "takeover").await;
+    }
+
+    /// Saves the provenance of a message just sent under its final id, once TDLib gives it.
+    pub fn record_provenance(
+        &self,
+        db: &Database,
+        provenance: MessageProvenance,
+    ) -> AlterResult<()> {
+        let key = (provenance.chat_id, provenance.message_id);
+        let mut sending = self.sending.lock().unwrap();
+        match sending.remove(&key) {
+            Some(Sending::Sent(message_id, _)) => db.save(MessageProvenance {
+                message_id,
+                ..provenance
+            }),
+            _ => {
+                sending.insert(key, Sending::Provenance(provenance, time::Instant::now()));
+                Ok(())
+            }
+        }
+    }
+
+    /// A message sent by this client got its final id.
+    pub fn message_sent(
+        &self,
+        db: &Database,
+        chat_id: i64,
+        old_message_id: i64,
+        message_id: i64,
+    ) -> AlterResult<()> {
+        let mut sending = self.sending.lock().unwrap();
+        // Failed messages never get a final id
+        sending.retain(|_, sending| match sending {
+            Sending::Provenance(_, since) | Sending::Sent(_, since) => {
+                since.elapsed() < SENDING_EXPIRY
+            }
+        });
+        match sending.remove(&(chat_id, old_message_id)) {
+            Some(Sending::Provenance(provenance, _)) => db.save(MessageProvenance {
+                message_id,
+                ..provenance
+            }),
+            _ => {
+                sending.insert(
+                    (chat_id, old_message_id),
+                    Sending::Sent(message_id, time::Instant::now()),
+                );
+                Ok(())
+            }
+        }
+    }
+
+    fn is_taken_over(&self, chat_id: i64) -> bool {
+        if self.taken_over_for(chat_id).is_some() {
+            return true;
+        }
+        self.taken_over.lock().unwrap().remove(&chat_id);
+        false
+    }
+
+    /// Answers a message, in place of the answer being written in its chat.
+    pub async fn answer(
+        self: &Arc<Self>,
+        db: Database,
+        config: Arc<Config>,
+        account: AccountConfig,
+        session: Session,
+        message: Message,
+        span: Span,
+    ) {
+        self.interrupt(message.chat_id, "newer_message").await;
+        let (interrupt_tx, interrupt_rx) = oneshot::channel();
+        self.start(message.chat_id, message.id, interrupt_tx);
+        tokio::spawn(
+            cancelable_thought(
+                db,
+                config,
+                account,
+                self.clone(),
+                session,
+                message,
+                interrupt_rx,
+            )
+            .instrument(span),
+        );
+    }
+
+    fn start(&self, chat_id: i64, message_id: i64, interrupt_tx: Interrupt) {
+        self.publish(Event::ThoughtStarted {
+            chat_id,
+            message_id,
+        });
+        self.thoughts.lock().unwrap().insert(
+            chat_id,
+            Thought {
+                message_id,
+                started_at: utils::unix_time(),
+                interrupt_tx,
+            },
+        );
+    }
+
+    fn finish(&self, chat_id: i64, message_id: i64, outcome: &'static str) {
+        self.publish(Event::ThoughtFinished {
+            chat_id,
+            message_id,
+            outcome,
+        });
+        let mut thoughts = self.thoughts.lock().unwrap();
+        if thoughts
+            .get(&chat_id)
+            .is_some_and(|thought| thought.message_id == message_id)
+        {
+            thoughts.remove(&chat_id);
+        }
+    }
+}
 
 pub async fn run(
-    db: Arc<Mutex<Database>>,
-    model_name: String,
-    mut message_rx: mpsc::UnboundedReceiver<Message>,
+    db: Database,
+    account_name: String,
+    config_rx: watch::Receiver<Arc<Config>>,
+    control: Arc<Control>,
+    mut update_rx: queue::Receiver,
     client_id: i32,
     mut shutdown_rx: broadcast::Receiver<()>,
 ) -> AlterResult<()> {
     info!("Start listening for messages");
-    let User::User(me) = functions::get_me(client_id).await.unwrap();
-    let mut thoughts: HashMap<i64, oneshot::Sender<oneshot::Sender<()>>> = HashMap::new();
+    let User::User(me) = functions::get_me(client_id)
+        .await
+        .inspect_err(metrics::tdlib_error)?;
+    let session = Session {
+        client_id,
+        me_id: me.id,
+    };
+    *control.session.lock().unwrap() = Some(session);
 
     loop {
         tokio::select! {
-            Some(message) = message_rx.recv() => {
-                let failsafe = {
-                    // Skip messages from me
-                    let _user_id = match message.sender_id {
-                        MessageSender::User(MessageSenderUser { user_id }) => if user_id == me.id {
+            Some((update, _)) = update_rx.recv() => {
+                let config = config_rx.borrow().clone();
+                let takeover_pause = time::Duration::from_secs(config.ai.takeover_pause_minutes * 60);
+                match update {
+                    Update::NewMessage(UpdateNewMessage { message }) => {
+                        // Saved Messages double as a command line
+                        if message.chat_id == me.id {
+                            let db = db.clone();
+                            let control = control.clone();
+                            tokio::spawn(async move {
+                                if let Err(e) = commands::handle(db, &control, message, client_id).await {
+                                    error!("{e:#?}");
+                                }
+                            });
                             continue;
-                        } else {
+                        }
+
+                        let failsafe = {
+                            // Skip messages from me
+                            let user_id = match message.sender_id {
+                                MessageSender::User(MessageSenderUser { user_id }) => if user_id == me.id {
+                                    // Messages sent by this client show up while still pending,
+                                    // those written on another device are already sent
+                                    if message.sending_state.is_none() {
+                                        control.take_over(message.chat_id, takeover_pause).await;
+                                    }
+                                    continue;
+                                } else {
+                                    user_id
+                                },
+                                _ => {
+                                    debug!(chat_id = message.chat_id, message_id = message.id, "Not sent by a user");
+                                    skip(&Span::none(), &account_name, "not_user");
+                                    continue;
+                                }
+                            };
+
+                            let span = message_span(&account_name, &message);
+                            let chat_name = utils::chat_display_name(&db, message.chat_id).await;
+                            let sender_name = utils::user_display_name(&db, user_id).await;
                             info!(
-                                "[{}] {}: {}",
-                                utils::chat_display_name(db.clone(), message.chat_id),
-                                utils::user_display_name(db.clone(), user_id),
+                                parent: &span,
+                                chat = %chat_name,
+                                sender = %sender_name,
+                                "{}",
                                 utils::message_text(&message).unwrap_or_else(|| "(Not text)".into()),
                             );
-                            user_id
-                        },
-                        _ => continue,
-                    };
 
-                    if !is_addressed_to_me(db.clone(), &me, &message) {
-                        continue;
-                    }
+                            if !is_addressed_to_me(&db, &me, &message).await {
+                                skip(&span, &account_name, "not_addressed");
+                                continue;
+                            }
 
-                    if let Some(interrupt_tx) = thoughts.remove(&message.chat_id) {
-                        if !interrupt_tx.is_closed() {
-                            let (interrupt_ack_tx, interrupt_ack_rx) = tokio::sync::oneshot::channel();
-                            interrupt_tx.send(interrupt_ack_tx).unwrap();
-                            let _ = interrupt_ack_rx.await;
-                        }
-                    }
+                            if control.is_taken_over(message.chat_id) {
+                                debug!(parent: &span, "Paused, I am answering myself");
+                                skip(&span, &account_name, "paused");
+                                continue;
+                            }
 
-                    let (interrupt_tx, interrupt_rx) = tokio::sync::oneshot::channel();
-                    let chat_id = message.chat_id;
-                    tokio::spawn(cancelable_thought(db.clone(), model_name.clone(), me.id, message, client_id, interrupt_rx));
-                    thoughts.insert(chat_id, interrupt_tx);
-                    Ok(())
-                } as AlterResult<()>;
+                            match db.load::<ChatReplyMode>(message.chat_id).await {
+                                Ok(Some(ChatReplyMode { mode: ReplyMode::Paused, .. })) => {
+                                    debug!(parent: &span, "Replies are paused in this chat");
+                                    skip(&span, &account_name, "reply_mode");
+                                    continue;
+                                }
+                                Ok(_) => (),
+                                // Better left unanswered than answered while maybe paused
+                                Err(e) => {
+                                    error!(parent: &span, "{e:#?}");
+                                    skip(&span, &account_name, "reply_mode");
+                                    continue;
+                                }
+                            }
 
-                if let Err(e) = failsafe {
-                    error!("{e:#?}");
+                            let Some(account) = config.account(&account_name) else {
+                                continue;
+                            };
+                            // Only the last of several messages in a row is answered
+                            control.answer(db.clone(), config, account, session, message, span).await;
+                            Ok(())
+                        } as AlterResult<()>;
+
+                        if let Err(e) = failsafe {
+                            error!("{e:#?}");
+                        }
+                    }
+                    Update::ChatAction(UpdateChatAction {
+                        chat_id,
+                        sender_id: MessageSender::User(MessageSenderUser { user_id }),
+                        action,
+                        ..
+                    }) if user_id == me.id && chat_id != me.id && action != ChatAction::Cancel => {
+                        control.take_over(chat_id, takeover_pause).await;
+                    }
+                    _ => {}
                 }
             },
             _ = shutdown_rx.recv() => {
@@ -88,20 +433,39 @@ pub async fn run(
     Ok(())
 }
 
-fn is_addressed_to_me(
-    db: Arc<Mutex<Database>>,
-    me: &tdlib::types::User,
-    message: &Message,
-) -> bool {
+pub fn message_span(account_name: &str, message: &Message) -> Span {
+    info_span!(
+        "message",
+        account = %account_name,
+        chat_id = message.chat_id,
+        message_id = message.id,
+        model = field::Empty,
+        prompt_size = field::Empty,
+        outcome = field::Empty,
+        reason = field::Empty,
+    )
+}
+
+fn skip(span: &Span, account_name: &str, reason: &str) {
+    span.record("outcome", "skipped").record("reason", reason);
+    metrics::increment(
+        metrics::MESSAGES_SKIPPED,
+        &[("account", account_name), ("reason", reason)],
+    );
+}
+
+async fn is_addressed_to_me(db: &Database, me: &tdlib::types::User, message: &Message) -> bool {
     if message.chat_id < 0 {
         if let Some(usernames) = &me.usernames {
             utils::message_text(message)
                 .unwrap_or_default()
                 .contains(&format!("@{}", usernames.editable_username))
-        } else if let Some(MessageReplyTo::Message(MessageReplyToMessage { message_id, .. })) =
-            message.reply_to
+        } else if let Some(MessageReplyTo::Message(MessageReplyToMessage {
+            chat_id,
+            message_id,
+        })) = message.reply_to
         {
-            match db.lock().unwrap().load::<MessageWrapper>(message_id) {
+            match db.load::<MessageWrapper>((chat_id, message_id)).await {
                 Ok(Some(reply_to)) => {
                     if let MessageSender::User(MessageSenderUser { user_id }) =
                         <MessageWrapper as Into<Message>>::into(reply_to).sender_id
@@ -126,17 +490,20 @@ fn is_addressed_to_me(
 }
 
 async fn thought(
-    db: Arc<Mutex<Database>>,
-    model_name: String,
+    db: Database,
+    config: Arc<Config>,
+    account: AccountConfig,
+    control: Arc<Control>,
     me_id: i64,
     message: Message,
     client_id: i32,
 ) -> AlterResult<()> {
     let now = time::Instant::now();
-    let question = utils::message_text(&message).unwrap_or_else(|| "Salut".into());
-    let answer = if message.chat_id < 0 {
+    let question =
+        utils::message_text(&message).unwrap_or_else(|| config.ai.fallback_message.clone());
+    let prompt = if message.chat_id < 0 {
         // Group chat
-        ollama::request(&model_name, &question).await?
+        ollama::request(account.model(&config), &question)
     } else {
         // Private chat
         functions::view_messages(
@@ -146,51 +513,202 @@ async fn thought(
             true,
             client_id,
         )
-        .await?;
-        ollama::chat(db.clone(), me_id, message.chat_id, None, &model_name)
-            .await?
-            .content
+        .await
+        .inspect_err(metrics::tdlib_error)?;
+        let exemplars = exemplars::prompt_section(&db, &message).await?;
+        ollama::chat(&db, &config, &account, me_id, message.chat_id, exemplars).await?
     };
-    simulate_waiting(
-        &question,
-        &answer,
-        now.elapsed(),
-        message.chat_id,
-        message.message_thread_id,
-        client_id,
-    )
-    .await?;
-    send_message(message, answer, client_id).await
+    Span::current()
+        .record("model", prompt.model_name.as_str())
+        .record("prompt_size", prompt.prompt_size);
+    let (chat_id, message_id) = (message.chat_id, message.id);
+    // Recorded before generating, so that an inference abandoned halfway is kept too
+    let mut cancel_guard = CancelGuard(None);
+    if config.audit.enabled {
+        db.save(InferenceAudit {
+            id: None,
+            chat_id,
+            message_id,
+            model: prompt.model_name.clone(),
+            endpoint: prompt.endpoint,
+            request: prompt.request.clone(),
+            completion: String::new(),
+            prompt_tokens: 0,
+            completion_tokens: 0,
+            inference_ms: 0,
+            waiting_ms: None,
+            outcome: Outcome::Pending,
+            created_at: utils::unix_time(),
+        })?;
+        cancel_guard = CancelGuard(Some((db.clone(), chat_id, message_id)));
+    }
+    let result = async {
+        let generation_start = time::Instant::now();
+        let (answer, generation) = ollama::complete(&config.ollama, prompt)
+            .instrument(info_span!("inference"))
+            .await?;
+        let latency = generation_start.elapsed();
+        if config.audit.enabled {
+            let completion = answer.clone();
+            let (prompt_tokens, completion_tokens) = (
+                generation.prompt_tokens as i64,
+                generation.completion_tokens as i64,
+            );
+            let inference_ms = generation.latency.as_millis() as i64;
+            db.write(move |conn| {
+                InferenceAudit::answer(
+                    conn,
+                    chat_id,
+                    message_id,
+                    &completion,
+                    prompt_tokens,
+                    completion_tokens,
+                    inference_ms,
+                )
+            })?;
+        }
+        let waiting_start = time::Instant::now();
+        simulate_waiting(
+            &config.ai,
+            &question,
+            &answer,
+            now.elapsed(),
+            message.chat_id,
+            message.message_thread_id,
+            client_id,
+        )
+        .instrument(info_span!("waiting"))
+        .await?;
+        let waiting = waiting_start.elapsed();
+        metrics::observe(
+            metrics::TYPING_DELAY,
+            &[("account", &account.name)],
+            waiting,
+        );
+        let sent = send_message(message, answer, client_id)
+            .instrument(info_span!("sending"))
+            .await?;
+        let waiting_ms = waiting.as_millis() as i64;
+        db.write(move |conn| {
+            InferenceAudit::finish(conn, chat_id, message_id, Outcome::Sent, Some(waiting_ms))
+        })?;
+        control.record_provenance(
+            &db,
+            MessageProvenance {
+                chat_id: sent.chat_id,
+                message_id: sent.id,
+                model: generation.model_name,
+                prompt_hash: generation.prompt_hash,
+                latency_ms: latency.as_millis() as i64,
+                approval: Approval::Auto,
+                created_at: utils::unix_time(),
+            },
+        )
+    }
+    .await;
+    // Ended, whatever became of it is recorded by the caller
+    cancel_guard.disarm();
+    result
+}
+
+/// Records the inference of a thought as cancelled when the thought is dropped before it ends.
+struct CancelGuard(Option<(Database, i64, i64)>);
+
+impl CancelGuard {
+    fn disarm(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        let Some((db, chat_id, message_id)) = self.0.take() else {
+            return;
+        };
+        let cancelled = db.write(move |conn| {
+            InferenceAudit::finish(conn, chat_id, message_id, Outcome::Cancelled, None)
+        });
+        if let Err(e) = cancelled {
+            error!("{e:#?}");
+        }
+    }
 }
 
 async fn cancelable_thought(
-    db: Arc<Mutex<Database>>,
-    model_name: String,
-    me_id: i64,
+    db: Database,
+    config: Arc<Config>,
+    account: AccountConfig,
+    control: Arc<Control>,
+    session: Session,
     message: Message,
-    client_id: i32,
-    interrupt_rx: tokio::sync::oneshot::Receiver<tokio::sync::oneshot::Sender<()>>,
+    interrupt_rx: oneshot::Receiver<(&'static str, oneshot::Sender<()>)>,
 ) -> i64 {
-    let chat_id = message.chat_id;
-    debug!("[{chat_id}] Handling message");
-    let mut thought_handle = tokio::spawn(thought(db, model_name, me_id, message, client_id));
+    let (chat_id, message_id) = (message.chat_id, message.id);
+    let account_name = account.name.clone();
+    let span = Span::current();
+    debug!("Handling message");
+    let mut thought_handle = tokio::spawn(
+        thought(
+            db.clone(),
+            config,
+            account,
+            control.clone(),
+            session.me_id,
+            message,
+            session.client_id,
+        )
+        .instrument(span.clone()),
+    );
+    let audit = |outcome| {
+        if let Err(e) =
+            db.write(move |conn| InferenceAudit::finish(conn, chat_id, message_id, outcome, None))
+        {
+            error!("{e:#?}");
+        }
+    };
 
-    tokio::select! {
+    let outcome = tokio::select! {
         task_result = &mut thought_handle => match task_result {
-            Ok(Ok(_)) => {},
-            Ok(Err(e)) => error!("[{chat_id}] Failed to handle message: {e:#?}"),
-            Err(e) => error!("[{chat_id}] Task failure: {e:#?}"),
+            Ok(Ok(_)) => {
+                span.record("outcome", "answered");
+                info!("Answered");
+                metrics::increment(metrics::MESSAGES_ANSWERED, &[("account", &account_name)]);
+                "answered"
+            }
+            Ok(Err(e)) => {
+                span.record("outcome", "failed");
+                error!("Failed to handle message: {e:#?}");
+                audit(Outcome::Failed);
+                metrics::increment(metrics::MESSAGES_FAILED, &[("account", &account_name)]);
+                "failed"
+            }
+            Err(e) => {
+                span.record("outcome", "failed");
+                error!("Task failure: {e:#?}");
+                audit(Outcome::Failed);
+                metrics::increment(metrics::MESSAGES_FAILED, &[("account", &account_name)]);
+                "failed"
+            }
         },
-        Ok(interrupt_ack_tx) = interrupt_rx => {
+        Ok((reason, interrupt_ack_tx)) = interrupt_rx => {
             thought_handle.abort();
-            debug!("[{chat_id}] Interrupted");
+            span.record("outcome", "interrupted").record("reason", reason);
+            audit(Outcome::Interrupted);
+            debug!("Interrupted");
+            metrics::increment(
+                metrics::MESSAGES_INTERRUPTED,
+                &[("account", &account_name), ("reason", reason)],
+            );
             let _ = interrupt_ack_tx.send(());
+            "interrupted"
         },
-    }
+    };
+    control.finish(chat_id, message_id, outcome);
     chat_id
 }
 
 async fn simulate_waiting(
+    config: &AiConfig,
     message: &str,
     answer: &str,
     elapsed: time::Duration,
@@ -205,18 +723,24 @@ async fn simulate_waiting(
         |s: &str| (s.chars().filter(|c| word_delimiters.contains(c)).count() + 1) as f64;
     let min_max_wait = |w_nb, (min, max)| (w_nb / max * 60. * 1000., w_nb / min * 60. * 1000.);
 
-    let (reading_min, reading_max) =
-        min_max_wait(words_number(message), (READING_WPM_MIN, READING_WPM_MAX));
-    let (thinking_min, thinking_max) =
-        min_max_wait(words_number(answer), (THINKING_WPM_MIN, THINKING_WPM_MAX));
+    let (reading_min, reading_max) = min_max_wait(
+        words_number(message),
+        (config.reading_wpm_min, config.reading_wpm_max),
+    );
+    let (thinking_min, thinking_max) = min_max_wait(
+        words_number(answer),
+        (config.thinking_wpm_min, config.thinking_wpm_max),
+    );
     utils::sleep_ms(
         utils::rand_between(reading_min as u64, reading_max as u64)
             + utils::rand_between(thinking_min as u64, thinking_max as u64),
     )
     .await;
 
-    let (typing_min, typing_max) =
-        min_max_wait(words_number(answer), (TYPING_WPM_MIN, TYPING_WPM_MAX));
+    let (typing_min, typing_max) = min_max_wait(
+        words_number(answer),
+        (config.typing_wpm_min, config.typing_wpm_max),
+    );
     let typing_wait = utils::rand_between(typing_min as u64, typing_max as u64);
     let mut typing = std::pin::pin!(utils::sleep_ms(
         if typing_wait < elapsed.as_millis() as u64 {
@@ -228,13 +752,16 @@ async fn simulate_waiting(
     loop {
         tokio::select! {
             _ = &mut typing => break,
-            _ = functions::send_chat_action(
+            response = functions::send_chat_action(
                 chat_id,
                 message_thread_id,
                 Some(tdlib::enums::ChatAction::Typing),
                 client_id,
             ) => {
-                let _ = utils::sleep_ms(5000).await;
+                if let Err(e) = &response {
+                    metrics::tdlib_error(e);
+                }
+                let _ = utils::sleep_ms(config.typing_action_interval_ms).await;
             },
         }
     }
@@ -242,19 +769,34 @@ async fn simulate_waiting(
     Ok(())
 }
 
-async fn send_message(message: Message, text: String, client_id: i32) -> AlterResult<()> {
-    info!("Sending message");
-    functions::send_message(
+pub async fn send_message(message: Message, text: String, client_id: i32) -> AlterResult<Message> {
+    let reply_to =
+        (message.chat_id < 0).then_some(MessageReplyTo::Message(MessageReplyToMessage {
+            chat_id: message.chat_id,
+            message_id: message.id,
+        }));
+    send_text(
         message.chat_id,
         message.message_thread_id,
-        if message.chat_id < 0 {
-            Some(MessageReplyTo::Message(MessageReplyToMessage {
-                chat_id: message.chat_id,
-                message_id: message.id,
-            }))
-        } else {
-            None
-        },
+        reply_to,
+        text,
+        client_id,
+    )
+    .await
+}
+
+pub async fn send_text(
+    chat_id: i64,
+    message_thread_id: i64,
+    reply_to: Option<MessageReplyTo>,
+    text: String,
+    client_id: i32,
+) -> AlterResult<Message> {
+    info!("Sending message");
+    let tdlib::enums::Message::Message(sent) = functions::send_message(
+        chat_id,
+        message_thread_id,
+        reply_to,
         None,
         InputMessageContent::InputMessageText(InputMessageText {
             text: FormattedText {
@@ -266,6 +808,7 @@ async fn send_message(message: Message, text: String, client_id: i32) -> AlterRe
         }),
         client_id,
     )
-    .await?;
-    Ok(())
+    .await
+    .inspect_err(metrics::tdlib_error)?;
+    Ok(sent)
 }
@@ -0,0 +1,299 @@
+use std::{sync::Arc, time::Instant};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{
+        sse::{Event as SseEvent, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{get, post, put},
+    Json, Router,
+};
+use futures::Stream;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    ai::{self, ThoughtState},
+    dashboard::{DashboardState, Failure},
+    database::Database,
+    error::AlterResult,
+    models::{
+        chat_reply_mode::ReplyMode,
+        decode_rows,
+        message_provenance::{Approval, MessageProvenance},
+        message_wrapper::MessageWrapper,
+        AutoRequestable,
+    },
+    search::{self, SearchHit},
+    utils,
+};
+
+const DEFAULT_HISTORY_LIMIT: i64 = 50;
+const MAX_HISTORY_LIMIT: i64 = 1000;
+const DEFAULT_SEARCH_LIMIT: usize = 20;
+
+type ApiState = State<Arc<DashboardState>>;
+
+/// The JSON API other tools drive alterego with, served next to the dashboard.
+pub fn routes() -> Router<Arc<DashboardState>> {
+    Router::new()
+        .route("/accounts", get(accounts))
+        .route("/accounts/:account/events", get(events))
+        .route("/accounts/:account/persona", put(set_persona))
+        .route("/accounts/:account/search", get(search))
+        .route("/accounts/:account/thoughts", get(thoughts))
+        .route(
+            "/accounts/:account/chats/:chat_id/messages",
+            get(history).post(send),
+        )
+        .route("/accounts/:account/chats/:chat_id/model", put(set_model))
+        .route("/accounts/:account/chats/:chat_id/reply", post(reply))
+        .route(
+            "/accounts/:account/chats/:chat_id/reply-mode",
+            put(set_reply_mode),
+        )
+}
+
+#[derive(Serialize)]
+struct AccountState {
+    name: String,
+    logged_in: bool,
+}
+
+async fn accounts(State(state): ApiState) -> Json<Vec<AccountState>> {
+    Json(
+        state
+            .accounts()
+            .iter()
+            .map(|account| AccountState {
+                name: account.name.clone(),
+                logged_in: account.control.session().is_some(),
+            })
+            .collect(),
+    )
+}
+
+/// Streams the events of an account as server-sent events, one JSON object each.
+async fn events(
+    State(state): ApiState,
+    Path(account_name): Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<SseEvent, axum::Error>>>, Failure> {
+    let events_rx = state.account(&account_name)?.control.subscribe();
+    let stream = futures::stream::unfold(events_rx, |mut events_rx| async move {
+        loop {
+            match events_rx.recv().await {
+                Ok(event) => return Some((SseEvent::default().json_data(event), events_rx)),
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("An event stream fell behind, {missed} events were dropped")
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+#[derive(Deserialize)]
+struct PersonaRequest {
+    /// Goes back to the configured persona when missing
+    persona: Option<String>,
+}
+
+async fn set_persona(
+    State(state): ApiState,
+    Path(account_name): Path<String>,
+    Json(request): Json<PersonaRequest>,
+) -> Result<StatusCode, Failure> {
+    state
+        .account(&account_name)?
+        .set_persona(request.persona)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    /// Words all found in the matching messages
+    query: String,
+    limit: Option<usize>,
+}
+
+async fn search(
+    State(state): ApiState,
+    Path(account_name): Path<String>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<Vec<SearchHit>>, Failure> {
+    let account = state.account(&account_name)?;
+    let limit = query.limit.unwrap_or(DEFAULT_SEARCH_LIMIT);
+    Ok(Json(
+        search::search(&account.db, &query.query, limit).await?,
+    ))
+}
+
+async fn thoughts(
+    State(state): ApiState,
+    Path(account_name): Path<String>,
+) -> Result<Json<Vec<ThoughtState>>, Failure> {
+    Ok(Json(state.account(&account_name)?.control.thoughts()))
+}
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    /// Only the messages older than this message id, i.e. the first one of the previous page
+    before_id: Option<i64>,
+    limit: Option<i64>,
+}
+
+/// The last messages of a chat, oldest first.
+async fn history(
+    State(state): ApiState,
+    Path((account_name, chat_id)): Path<(String, i64)>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<Vec<MessageWrapper>>, Failure> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_HISTORY_LIMIT)
+        .clamp(1, MAX_HISTORY_LIMIT);
+    let account = state.account(&account_name)?;
+    Ok(Json(
+        account
+            .recent_messages(chat_id, query.before_id, limit)
+            .await?,
+    ))
+}
+
+#[derive(Deserialize)]
+struct SendRequest {
+    text: String,
+}
+
+#[derive(Serialize)]
+struct MessageReference {
+    chat_id: i64,
+    message_id: i64,
+}
+
+/// Writes a message as me.
+async fn send(
+    State(state): ApiState,
+    Path((account_name, chat_id)): Path<(String, i64)>,
+    Json(request): Json<SendRequest>,
+) -> Result<Json<MessageReference>, Failure> {
+    let account = state.account(&account_name)?;
+    let session = account.control.session().ok_or(Failure::Unavailable)?;
+    if request.text.trim().is_empty() {
+        return Err(Failure::BadRequest("The message is empty".into()));
+    }
+    let start = Instant::now();
+    let prompt_hash = utils::hash(&request.text);
+    let sent = ai::send_text(chat_id, 0, None, request.text, session.client_id).await?;
+    // Sent on behalf of me by one of my tools, which is not me writing
+    account.control.record_provenance(
+        &account.db,
+        MessageProvenance {
+            chat_id: sent.chat_id,
+            message_id: sent.id,
+            model: "api".into(),
+            prompt_hash,
+            latency_ms: start.elapsed().as_millis() as i64,
+            approval: Approval::Approved,
+            created_at: utils::unix_time(),
+        },
+    )?;
+    Ok(Json(MessageReference {
+        chat_id: sent.chat_id,
+        message_id: sent.id,
+    }))
+}
+
+#[derive(Deserialize)]
+struct ModelRequest {
+    /// Goes back to the model of the account when missing
+    model: Option<String>,
+}
+
+async fn set_model(
+    State(state): ApiState,
+    Path((account_name, chat_id)): Path<(String, i64)>,
+    Json(request): Json<ModelRequest>,
+) -> Result<StatusCode, Failure> {
+    state
+        .account(&account_name)?
+        .set_model(chat_id, request.model)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Answers the last message received in a chat, even when its replies are paused. The answer
+/// is written in the background, its progress shows up in the events.
+async fn reply(
+    State(state): ApiState,
+    Path((account_name, chat_id)): Path<(String, i64)>,
+) -> Result<Response, Failure> {
+    let account = state.account(&account_name)?;
+    let session = account.control.session().ok_or(Failure::Unavailable)?;
+    let config = state.config();
+    let account_config = config.account(&account_name).ok_or(Failure::NotFound)?;
+    let message = last_received(&account.db, chat_id)
+        .await?
+        .ok_or_else(|| Failure::BadRequest("No message to answer in this chat".into()))?;
+    let reference = MessageReference {
+        chat_id,
+        message_id: message.id,
+    };
+    let span = ai::message_span(&account_name, &message);
+    account
+        .control
+        .answer(
+            account.db.clone(),
+            config,
+            account_config,
+            session,
+            message.into(),
+            span,
+        )
+        .await;
+    Ok((StatusCode::ACCEPTED, Json(reference)).into_response())
+}
+
+#[derive(Deserialize)]
+struct ReplyModeRequest {
+    /// `auto` or `paused`
+    mode: String,
+}
+
+async fn set_reply_mode(
+    State(state): ApiState,
+    Path((account_name, chat_id)): Path<(String, i64)>,
+    Json(request): Json<ReplyModeRequest>,
+) -> Result<StatusCode, Failure> {
+    let mode = request
+        .mode
+        .parse::<ReplyMode>()
+        .map_err(Failure::BadRequest)?;
+    state
+        .account(&account_name)?
+        .set_reply_mode(chat_id, mode)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn last_received(db: &Database, chat_id: i64) -> AlterResult<Option<MessageWrapper>> {
+    let messages = db
+        .execute(move |conn| {
+            decode_rows::<MessageWrapper>(
+                MessageWrapper::TABLE,
+                conn.prepare(
+                    r#"SELECT rowid AS row_id, * FROM MESSAGES
+                WHERE chat_id = :chat_id AND NOT is_outgoing
+                ORDER BY date DESC, id DESC LIMIT 1"#,
+                )?
+                .query(rusqlite::named_params! { ":chat_id": chat_id })?,
+            )
+        })
+        .await?;
+    Ok(messages.into_iter().next().transpose()?)
+}
@@ -1,84 +1,99 @@
-use std::pin::Pin;
+use std::{collections::HashMap, pin::Pin};
 
-use dialoguer::{theme::ColorfulTheme, Input};
-use futures::{Future, StreamExt};
-use log::{debug, error, info};
+use futures::{future, Future};
+use log::{debug, error, info, warn};
 use tdlib::{
     enums::{AuthorizationState, Update},
     functions,
-    types::{Message, UpdateAuthorizationState},
+    types::UpdateAuthorizationState,
 };
 use tokio::{
     signal::unix,
-    sync::{broadcast, mpsc},
+    sync::{broadcast, Mutex},
 };
 
-use crate::{error::AlterResult, update_stream::UpdateStream};
+use crate::{
+    auth::{self, Authenticator},
+    config::{QueueConfig, TelegramConfig},
+    error::AlterResult,
+    metrics, queue,
+    update_stream::UpdateStream,
+};
 
-pub struct ApplicationData {
-    pub client_id: i32,
-    pub auth_rx: mpsc::UnboundedReceiver<AuthorizationState>,
-    pub message_rx: mpsc::UnboundedReceiver<Message>,
-    pub shutdown_rx: tokio::sync::broadcast::Receiver<()>,
+/// A Telegram account, logged in with its own TDLib client.
+pub struct Account {
+    pub name: String,
+    pub telegram: TelegramConfig,
+    pub authenticator: Authenticator,
 }
 
-unsafe impl Send for ApplicationData {}
-unsafe impl Sync for ApplicationData {}
-
 pub struct Application {
     app_id: i32,
     app_hash: String,
-    database_name: String,
+    accounts: Vec<Account>,
+    queues: QueueConfig,
 }
 
 impl Application {
-    pub fn new(app_id: i32, app_hash: &str, database_directory: &str) -> Self {
+    pub fn new(app_id: i32, app_hash: &str, accounts: Vec<Account>, queues: &QueueConfig) -> Self {
         Self {
             app_id,
             app_hash: app_hash.into(),
-            database_name: database_directory.into(),
+            accounts,
+            queues: queues.clone(),
         }
     }
 
+    /// Logs each account in then runs `f` for it, every account concurrently, given the
+    /// index of the account and the updates of its client.
     pub async fn run<F>(&self, f: F) -> AlterResult<()>
     where
-        F: for<'a> FnOnce(
-            &'a mut tokio::sync::mpsc::UnboundedReceiver<(Update, i32)>,
+        F: for<'a> Fn(
+            usize,
+            &'a mut queue::Receiver,
             i32,
             tokio::sync::broadcast::Receiver<()>,
         ) -> Pin<Box<dyn Future<Output = AlterResult<()>> + 'a>>,
     {
-        let client_id = tdlib::create_client();
-        debug!("Client ID '{client_id}' created");
+        let mut clients = Vec::new();
+        let mut routes = HashMap::new();
+        for account in &self.accounts {
+            let client_id = tdlib::create_client();
+            debug!("Client ID '{client_id}' created for '{}'", account.name);
+            let (update_tx, update_rx) =
+                queue::channel(self.queues.updates_capacity, "updates", &account.name);
+            routes.insert(client_id, update_tx);
+            clients.push((client_id, update_rx));
+        }
 
         let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
-        let (update_tx, mut update_rx) = mpsc::unbounded_channel();
 
-        // The update receiver is a separate task that listens for updates from the TDLib client
+        // The update receiver is a separate thread that listens for updates from the TDLib clients,
+        // routed to their account by a task until it stops
         // It must be spawned before any other task that sends updates to the client
+        let (update_stream, mut update_stream_rx) =
+            UpdateStream::spawn(self.queues.receive_capacity)?;
         let update_handler = tokio::spawn(async move {
-            let mut update_stream = UpdateStream;
-            loop {
-                tokio::select! {
-                    Some((update, client_id)) = update_stream.next() => {
-                        if let Err(e) = update_tx.send((update, client_id)) {
+            while let Some((update, client_id)) = update_stream_rx.recv().await {
+                match routes.get(&client_id) {
+                    Some(update_tx) => {
+                        if let Err(e) = update_tx.send((update, client_id)).await {
                             error!("{e:#?}");
                         }
                     }
+                    None => warn!("Update for unknown client '{client_id}'"),
                 }
             }
         });
 
         let mut sigint = unix::signal(unix::SignalKind::interrupt())?;
         let mut sigterm = unix::signal(unix::SignalKind::terminate())?;
-        let mut sighup = unix::signal(unix::SignalKind::hangup())?;
         let mut sigquit = unix::signal(unix::SignalKind::quit())?;
         let signal_handler = tokio::spawn(async move {
             loop {
                 tokio::select! {
                     _ = sigint.recv() => shutdown(&shutdown_tx),
                     _ = sigterm.recv() => shutdown(&shutdown_tx),
-                    _ = sighup.recv() => shutdown(&shutdown_tx),
                     _ = sigquit.recv() => shutdown(&shutdown_tx),
                 }
             }
@@ -91,46 +106,89 @@ impl Application {
             }
         });
 
-        self.login(&mut update_rx, client_id, shutdown_rx.resubscribe())
-            .await?;
-
-        if let Err(e) = f(&mut update_rx, client_id, shutdown_rx.resubscribe()).await {
-            error!("{e:#?}");
-        }
+        // An account starts as soon as it is logged in, so that its updates never wait for
+        // the logins of the others
+        let prompt_lock = Mutex::new(());
+        let accounts = self.accounts.iter().zip(clients.iter_mut()).enumerate();
+        let logins =
+            future::join_all(accounts.map(|(index, (account, (client_id, update_rx)))| {
+                let (f, prompt_lock) = (&f, &prompt_lock);
+                let shutdown_rx = shutdown_rx.resubscribe();
+                async move {
+                    let login = self.login(
+                        account,
+                        update_rx,
+                        *client_id,
+                        shutdown_rx.resubscribe(),
+                        prompt_lock,
+                    );
+                    if let Err(e) = login.await {
+                        error!("Could not log '{}' in: {e:#?}", account.name);
+                        return Err(e);
+                    }
+                    if let Err(e) = f(index, update_rx, *client_id, shutdown_rx).await {
+                        error!("{e:#?}");
+                    }
+                    Ok(())
+                }
+            }))
+            .await
+            .into_iter()
+            .collect::<AlterResult<Vec<_>>>();
 
-        self.close(&mut update_rx, client_id, shutdown_rx.resubscribe())
-            .await?;
+        let results = future::join_all(clients.iter_mut().map(|(client_id, update_rx)| {
+            self.close(update_rx, *client_id, shutdown_rx.resubscribe())
+        }))
+        .await;
+        results.into_iter().collect::<AlterResult<Vec<_>>>()?;
 
-        update_handler.abort();
+        update_stream.stop().await?;
+        update_handler.await?;
         signal_handler.abort();
-        Ok(())
+        logins.map(|_| ())
     }
 
     async fn login(
         &self,
-        update_rx: &mut tokio::sync::mpsc::UnboundedReceiver<(Update, i32)>,
+        account: &Account,
+        update_rx: &mut queue::Receiver,
         client_id: i32,
         mut shutdown_rx: tokio::sync::broadcast::Receiver<()>,
+        prompt_lock: &Mutex<()>,
     ) -> AlterResult<()> {
-        debug!("Logging in");
-        functions::set_log_verbosity_level(1, client_id).await?;
+        info!("Logging in '{}'", account.name);
+        functions::set_log_verbosity_level(account.telegram.log_verbosity, client_id)
+            .await
+            .inspect_err(metrics::tdlib_error)?;
 
+        // Held from the first prompt until logged in, as the prompts of several accounts
+        // would get mixed up
+        let mut prompt_guard = None;
         loop {
             tokio::select! {
                 Some((update, client_id)) = update_rx.recv() => match update {
-                    Update::AuthorizationState(UpdateAuthorizationState { authorization_state }) => match authorization_state {
-                        AuthorizationState::WaitTdlibParameters => set_tdlib_parameters(
-                            self.app_id,
-                            &self.app_hash,
-                            &self.database_name,
-                            client_id,
-                        ).await,
-                        AuthorizationState::WaitPhoneNumber => wait_phone_number(client_id).await,
-                        AuthorizationState::WaitCode(_) => wait_code(client_id).await,
-                        AuthorizationState::WaitPassword(_) => wait_password(client_id).await,
-                        AuthorizationState::Ready => break,
-                        _ => (),
-                    },
+                    Update::AuthorizationState(UpdateAuthorizationState { authorization_state }) => {
+                        if prompt_guard.is_none() && prompts(&authorization_state) {
+                            prompt_guard = Some(prompt_lock.lock().await);
+                        }
+                        match authorization_state {
+                            AuthorizationState::WaitTdlibParameters => set_tdlib_parameters(
+                                self.app_id,
+                                &self.app_hash,
+                                &account.telegram,
+                                client_id,
+                            ).await,
+                            AuthorizationState::WaitPhoneNumber => account.authenticator.wait_phone_number(client_id).await?,
+                            AuthorizationState::WaitCode(_) => account.authenticator.wait_code(client_id).await?,
+                            AuthorizationState::WaitPassword(_) => account.authenticator.wait_password(client_id).await?,
+                            AuthorizationState::WaitEmailAddress(_) => account.authenticator.wait_email_address(client_id).await?,
+                            AuthorizationState::WaitEmailCode(_) => account.authenticator.wait_email_code(client_id).await?,
+                            AuthorizationState::WaitRegistration(_) => account.authenticator.wait_registration(client_id).await?,
+                            AuthorizationState::WaitOtherDeviceConfirmation(confirmation) => auth::wait_other_device_confirmation(&confirmation.link),
+                            AuthorizationState::Ready => break,
+                            _ => (),
+                        }
+                    }
                     _ => (),
                 },
                 _ = shutdown_rx.recv() => {
@@ -140,18 +198,20 @@ impl Application {
             }
         }
 
-        debug!("Logged in");
+        debug!("Logged in '{}'", account.name);
         Ok(())
     }
 
     async fn close(
         &self,
-        update_rx: &mut tokio::sync::mpsc::UnboundedReceiver<(Update, i32)>,
+        update_rx: &mut queue::Receiver,
         client_id: i32,
         mut shutdown_rx: tokio::sync::broadcast::Receiver<()>,
     ) -> AlterResult<()> {
         info!("Closing connection");
-        functions::close(client_id).await?;
+        functions::close(client_id)
+            .await
+            .inspect_err(metrics::tdlib_error)?;
 
         loop {
             tokio::select! {
@@ -174,18 +234,29 @@ impl Application {
     }
 }
 
-fn ask_user(prompt: &str) -> AlterResult<String> {
-    Ok(Input::<String>::with_theme(&ColorfulTheme::default())
-        .with_prompt(prompt)
-        .interact_text()?
-        .trim()
-        .into())
+/// Whether the state waits on me, the prompts of one account at a time.
+fn prompts(authorization_state: &AuthorizationState) -> bool {
+    matches!(
+        authorization_state,
+        AuthorizationState::WaitPhoneNumber
+            | AuthorizationState::WaitCode(_)
+            | AuthorizationState::WaitPassword(_)
+            | AuthorizationState::WaitEmailAddress(_)
+            | AuthorizationState::WaitEmailCode(_)
+            | AuthorizationState::WaitRegistration(_)
+            | AuthorizationState::WaitOtherDeviceConfirmation(_)
+    )
 }
 
-async fn set_tdlib_parameters(app_id: i32, app_hash: &str, database_name: &str, client_id: i32) {
+async fn set_tdlib_parameters(
+    app_id: i32,
+    app_hash: &str,
+    telegram: &TelegramConfig,
+    client_id: i32,
+) {
     let response = functions::set_tdlib_parameters(
         false,
-        database_name.into(),
+        telegram.database_directory.clone(),
         Default::default(),
         Default::default(),
         true,
@@ -194,51 +265,18 @@ async fn set_tdlib_parameters(app_id: i32, app_hash: &str, database_name: &str,
         true,
         app_id,
         app_hash.into(),
-        "en".into(),
-        "Desktop".into(),
+        telegram.system_language_code.clone(),
+        telegram.device_model.clone(),
         Default::default(),
         env!("CARGO_PKG_VERSION").into(),
         false,
         true,
         client_id,
     )
-    .await;
-
-    if let Err(e) = response {
-        error!("{e:#?}");
-    }
-}
-
-async fn wait_phone_number(client_id: i32) {
-    while let Err(e) = functions::set_authentication_phone_number(
-        ask_user("Enter your phone number (include the country calling code):").unwrap(),
-        None,
-        client_id,
-    )
-    .await
-    {
-        error!("{e:#?}");
-    }
-}
-
-async fn wait_code(client_id: i32) {
-    while let Err(e) = functions::check_authentication_code(
-        ask_user("Enter the verification code:").unwrap(),
-        client_id,
-    )
     .await
-    {
-        error!("{e:#?}");
-    }
-}
+    .inspect_err(metrics::tdlib_error);
 
-async fn wait_password(client_id: i32) {
-    while let Err(e) = functions::check_authentication_password(
-        ask_user("Enter the password:").unwrap(),
-        client_id,
-    )
-    .await
-    {
+    if let Err(e) = response {
         error!("{e:#?}");
     }
 }
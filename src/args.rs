@@ -1,13 +1,130 @@
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
-#[derive(Parser, Debug)]
+use crate::{
+    auth::CodeSource,
+    export::{ExportFormat, Scrub},
+    utils,
+};
+
+#[derive(Parser, Debug, Clone)]
 pub struct Args {
-    #[arg(short, long, default_value = "db_me")]
-    pub tg_database_directory: String,
-    #[arg(short, long, default_value = "db.sqlite")]
-    pub database_path: PathBuf,
-    #[arg(short, long, default_value = "alter-mistral")]
-    pub model_name: String,
+    /// TOML configuration file, alterego.toml when it exists
+    #[arg(short, long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// Account to run the subcommands and the backfill on, the first one when missing
+    #[arg(long, value_name = "NAME")]
+    pub account: Option<String>,
+    #[arg(short, long)]
+    pub tg_database_directory: Option<String>,
+    #[arg(short, long)]
+    pub database_path: Option<PathBuf>,
+    #[arg(short, long)]
+    pub model_name: Option<String>,
+    #[arg(long)]
+    pub migrate_only: bool,
+    /// Backfill the history of these chats at startup
+    #[arg(long, value_name = "CHAT_ID", value_delimiter = ',')]
+    pub backfill: Vec<i64>,
+    /// Backfill the history of every private chat at startup
+    #[arg(long, conflicts_with = "backfill")]
+    pub backfill_private_chats: bool,
+    /// Minutes between two computations of the style profiles
+    #[arg(long)]
+    pub style_refresh_minutes: Option<u64>,
+    /// Minutes without auto-replies in a chat after I type or write in it myself
+    #[arg(long)]
+    pub takeover_pause_minutes: Option<u64>,
+    /// API id of the application, from https://my.telegram.org
+    #[arg(long)]
+    pub api_id: Option<i32>,
+    /// API hash of the application, from https://my.telegram.org
+    #[arg(long)]
+    pub api_hash: Option<String>,
+    /// JSON file holding the API id and hash, and the phone number, password, email address,
+    /// first and last name to log in with, overridden by the ALTEREGO_* environment variables.
+    /// With several accounts, only its API id and hash are used
+    #[arg(long, value_name = "PATH")]
+    pub auth_file: Option<PathBuf>,
+    /// Where to get the verification codes from: "prompt", a file or named pipe path, or a
+    /// local "http://ADDRESS:PORT" endpoint to POST them to
+    #[arg(long, value_name = "SOURCE", default_value = "prompt")]
+    pub auth_code: CodeSource,
+    /// Log in by confirming a link on another device instead of with a phone number
+    #[arg(long)]
+    pub qr_login: bool,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Inspect the configuration
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// List database rows that can no longer be decoded
+    Doctor,
+    /// List the deleted messages of a chat
+    Deleted { chat_id: i64 },
+    /// Search stored messages containing every given word
+    Search {
+        query: String,
+        #[arg(short, long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Export conversations as a JSONL fine-tuning dataset
+    Export(ExportArgs),
+    /// Import the result.json of a Telegram Desktop export
+    Import {
+        path: PathBuf,
+        /// Your user id, read from the export when it covers the whole account
+        #[arg(long)]
+        me: Option<i64>,
+    },
+    /// Ask another model to answer the last recorded prompt of a message, to compare with the
+    /// recorded answer
+    Replay {
+        chat_id: i64,
+        /// The message which was answered
+        message_id: i64,
+        #[arg(short, long)]
+        model: String,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ConfigCommand {
+    /// Validate the configuration and print it with every layer applied
+    Check,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ExportArgs {
+    /// Output file, standard output when missing
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    #[arg(short, long, value_enum, default_value_t = ExportFormat::Openai)]
+    pub format: ExportFormat,
+    /// Only export these chats, every private chat when missing
+    #[arg(long, value_name = "CHAT_ID", value_delimiter = ',')]
+    pub chat: Vec<i64>,
+    /// Only export messages sent from this date (YYYY-MM-DD, UTC)
+    #[arg(long, value_parser = utils::parse_date)]
+    pub since: Option<i64>,
+    /// Only export messages sent before this date (YYYY-MM-DD, UTC)
+    #[arg(long, value_parser = utils::parse_date)]
+    pub until: Option<i64>,
+    /// Minimum number of turns of a conversation, counting both sides
+    #[arg(long, default_value_t = 2)]
+    pub min_turns: usize,
+    /// Silence in minutes after which a new conversation starts
+    #[arg(long, default_value_t = 360)]
+    pub session_gap: i64,
+    /// System prompt prepended to every conversation
+    #[arg(long)]
+    pub system: Option<String>,
+    /// Personal data to replace with placeholders
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub scrub: Vec<Scrub>,
 }
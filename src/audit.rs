@@ -0,0 +1,75 @@
+use std::{sync::Arc, time::Duration};
+
+use log::{debug, error, info};
+use tokio::sync::watch;
+
+use crate::{
+    config::{Config, OllamaConfig},
+    database::Database,
+    error::AlterResult,
+    models::inference_audit::InferenceAudit,
+    ollama, utils,
+};
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Deletes the inference records older than the retention period, every hour.
+pub async fn run(db: Database, config_rx: watch::Receiver<Arc<Config>>) {
+    loop {
+        let retention_days = config_rx.borrow().audit.retention_days as i64;
+        let before = utils::unix_time() - retention_days * 24 * 60 * 60;
+        let pruned = db.write(move |conn| {
+            let pruned = InferenceAudit::prune(conn, before)?;
+            if pruned > 0 {
+                debug!("Pruned {pruned} inference records");
+            }
+            Ok(())
+        });
+        if let Err(e) = pruned {
+            error!("{e:#?}");
+        }
+        tokio::time::sleep(PRUNE_INTERVAL).await;
+    }
+}
+
+/// Asks another model to answer the last recorded prompt of a message, and prints both
+/// answers.
+pub async fn replay(
+    db: &Database,
+    config: &OllamaConfig,
+    chat_id: i64,
+    message_id: i64,
+    model_name: &str,
+) -> AlterResult<()> {
+    let audit = db
+        .execute(move |conn| InferenceAudit::select_latest(chat_id, message_id, conn))
+        .await?;
+    let Some(audit) = audit else {
+        println!("No inference recorded for message {message_id} of chat {chat_id}");
+        return Ok(());
+    };
+    println!(
+        "[{}] {} ({} ms, {} prompt tokens, {} completion tokens, {})",
+        audit.created_at,
+        audit.model,
+        audit.inference_ms,
+        audit.prompt_tokens,
+        audit.completion_tokens,
+        audit.outcome.as_str(),
+    );
+    println!("{}\n", audit.completion);
+
+    info!("Replaying the prompt with model '{model_name}'");
+    let (completion, generation) =
+        ollama::replay(config, audit.endpoint, &audit.request, model_name).await?;
+    println!(
+        "[{}] {} ({} ms, {} prompt tokens, {} completion tokens)",
+        utils::unix_time(),
+        generation.model_name,
+        generation.latency.as_millis(),
+        generation.prompt_tokens,
+        generation.completion_tokens,
+    );
+    println!("{completion}");
+    Ok(())
+}
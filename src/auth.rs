@@ -0,0 +1,399 @@
+use std::{
+    fs,
+    io::IsTerminal,
+    net::SocketAddr,
+    os::unix::fs::FileTypeExt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use axum::{
+    extract::{ConnectInfo, Query, State},
+    http::StatusCode,
+    Router,
+};
+use dialoguer::{theme::ColorfulTheme, Input};
+use log::{error, info, warn};
+use serde::Deserialize;
+use tdlib::{enums::EmailAddressAuthentication, functions, types::EmailAddressAuthenticationCode};
+use tokio::{net::TcpListener, sync::mpsc};
+
+use crate::{
+    error::{AlterResult, Error},
+    metrics,
+};
+
+const CODE_FILE_POLL: Duration = Duration::from_secs(1);
+
+/// What is needed to log in, read from a JSON file then overridden by the `ALTEREGO_*`
+/// environment variables. Anything missing but the API id and hash is prompted for.
+#[derive(Debug, Default, Deserialize)]
+pub struct Credentials {
+    pub api_id: Option<i32>,
+    pub api_hash: Option<String>,
+    pub phone_number: Option<String>,
+    pub password: Option<String>,
+    pub email_address: Option<String>,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+}
+
+impl Credentials {
+    /// The identity, unlike the API id and hash, is only taken from the environment when
+    /// asked to, as it belongs to a single account.
+    pub fn load(path: Option<&Path>, identity_from_env: bool) -> AlterResult<Self> {
+        let mut credentials: Credentials = match path {
+            Some(path) => serde_json::from_reader(fs::File::open(path)?)?,
+            None => Default::default(),
+        };
+        if let Ok(api_id) = std::env::var("ALTEREGO_API_ID") {
+            credentials.api_id = Some(
+                api_id
+                    .parse()
+                    .map_err(|_| Error::InvalidCredential("ALTEREGO_API_ID"))?,
+            );
+        }
+        if let Ok(api_hash) = std::env::var("ALTEREGO_API_HASH") {
+            credentials.api_hash = Some(api_hash);
+        }
+        if !identity_from_env {
+            return Ok(credentials);
+        }
+        for (variable, value) in [
+            ("ALTEREGO_PHONE_NUMBER", &mut credentials.phone_number),
+            ("ALTEREGO_PASSWORD", &mut credentials.password),
+            ("ALTEREGO_EMAIL_ADDRESS", &mut credentials.email_address),
+            ("ALTEREGO_FIRST_NAME", &mut credentials.first_name),
+            ("ALTEREGO_LAST_NAME", &mut credentials.last_name),
+        ] {
+            if let Ok(env_value) = std::env::var(variable) {
+                *value = Some(env_value);
+            }
+        }
+        Ok(credentials)
+    }
+
+    /// Only the API id and hash, shared by every account.
+    pub fn application(self) -> Self {
+        Self {
+            api_id: self.api_id,
+            api_hash: self.api_hash,
+            ..Default::default()
+        }
+    }
+}
+
+/// The application credentials from https://my.telegram.org. Without them, the ones
+/// embedded at build time from `app.id` and `app.hash` are used if the
+/// `embedded-credentials` feature is enabled.
+pub fn api_credentials(credentials: &Credentials) -> AlterResult<(i32, String)> {
+    let api_id = match credentials.api_id {
+        Some(api_id) => api_id,
+        None => embedded_api_id().ok_or(Error::MissingCredential(
+            "API id (--api-id, ALTEREGO_API_ID or \"api_id\" in the auth file)",
+        ))?,
+    };
+    let api_hash = match &credentials.api_hash {
+        Some(api_hash) => api_hash.clone(),
+        None => embedded_api_hash().ok_or(Error::MissingCredential(
+            "API hash (--api-hash, ALTEREGO_API_HASH or \"api_hash\" in the auth file)",
+        ))?,
+    };
+    if api_hash.len() != 32 || !api_hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(Error::InvalidCredential("API hash"));
+    }
+    Ok((api_id, api_hash))
+}
+
+#[cfg(feature = "embedded-credentials")]
+fn embedded_api_id() -> Option<i32> {
+    Some(include!("../app.id"))
+}
+
+#[cfg(not(feature = "embedded-credentials"))]
+fn embedded_api_id() -> Option<i32> {
+    None
+}
+
+#[cfg(feature = "embedded-credentials")]
+fn embedded_api_hash() -> Option<String> {
+    Some(include_str!("../app.hash").trim().into())
+}
+
+#[cfg(not(feature = "embedded-credentials"))]
+fn embedded_api_hash() -> Option<String> {
+    None
+}
+
+/// Where the verification codes come from.
+#[derive(Debug, Clone)]
+pub enum CodeSource {
+    Prompt,
+    /// A named pipe to read from, or a file to wait for, which is removed once read
+    File(PathBuf),
+    /// A local HTTP endpoint, the code being the body of a POST or the `code` parameter
+    Http(SocketAddr),
+}
+
+impl FromStr for CodeSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "prompt" {
+            Ok(CodeSource::Prompt)
+        } else if let Some(address) = s.strip_prefix("http://") {
+            let address = address
+                .trim_end_matches('/')
+                .parse::<SocketAddr>()
+                .map_err(|e| format!("invalid address '{address}': {e}"))?;
+            // Whoever can reach it can log in as me
+            if !address.ip().is_loopback() {
+                return Err(format!("'{address}' is not a local address"));
+            }
+            Ok(CodeSource::Http(address))
+        } else {
+            Ok(CodeSource::File(s.into()))
+        }
+    }
+}
+
+pub struct Authenticator {
+    pub credentials: Credentials,
+    pub code_source: CodeSource,
+    pub qr_login: bool,
+}
+
+impl Authenticator {
+    pub async fn wait_phone_number(&self, client_id: i32) -> AlterResult<()> {
+        if self.qr_login && self.credentials.phone_number.is_none() {
+            info!("Requesting a QR code login");
+            return Ok(functions::request_qr_code_authentication(vec![], client_id)
+                .await
+                .inspect_err(metrics::tdlib_error)?);
+        }
+        loop {
+            let phone_number = configured_or_ask(
+                &self.credentials.phone_number,
+                "phone number",
+                "Enter your phone number (include the country calling code):",
+            )?;
+            match functions::set_authentication_phone_number(phone_number, None, client_id)
+                .await
+                .inspect_err(metrics::tdlib_error)
+            {
+                Ok(()) => return Ok(()),
+                Err(e) if self.credentials.phone_number.is_none() => error!("{e:#?}"),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    pub async fn wait_code(&self, client_id: i32) -> AlterResult<()> {
+        loop {
+            let code = self.code("Enter the verification code:").await?;
+            match functions::check_authentication_code(code, client_id)
+                .await
+                .inspect_err(metrics::tdlib_error)
+            {
+                Ok(()) => return Ok(()),
+                Err(e) => error!("{e:#?}"),
+            }
+        }
+    }
+
+    pub async fn wait_password(&self, client_id: i32) -> AlterResult<()> {
+        loop {
+            let password = configured_or_ask(
+                &self.credentials.password,
+                "password",
+                "Enter the password:",
+            )?;
+            match functions::check_authentication_password(password, client_id)
+                .await
+                .inspect_err(metrics::tdlib_error)
+            {
+                Ok(()) => return Ok(()),
+                Err(e) if self.credentials.password.is_none() => error!("{e:#?}"),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    pub async fn wait_email_address(&self, client_id: i32) -> AlterResult<()> {
+        loop {
+            let email_address = configured_or_ask(
+                &self.credentials.email_address,
+                "email address",
+                "Enter your email address:",
+            )?;
+            match functions::set_authentication_email_address(email_address, client_id)
+                .await
+                .inspect_err(metrics::tdlib_error)
+            {
+                Ok(()) => return Ok(()),
+                Err(e) if self.credentials.email_address.is_none() => error!("{e:#?}"),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    pub async fn wait_email_code(&self, client_id: i32) -> AlterResult<()> {
+        loop {
+            let code = self
+                .code("Enter the code sent to your email address:")
+                .await?;
+            match functions::check_authentication_email_code(
+                EmailAddressAuthentication::Code(EmailAddressAuthenticationCode { code }),
+                client_id,
+            )
+            .await
+            .inspect_err(metrics::tdlib_error)
+            {
+                Ok(()) => return Ok(()),
+                Err(e) => error!("{e:#?}"),
+            }
+        }
+    }
+
+    pub async fn wait_registration(&self, client_id: i32) -> AlterResult<()> {
+        warn!("This phone number has no account yet, registering it");
+        let first_name = configured_or_ask(
+            &self.credentials.first_name,
+            "first name",
+            "Enter your first name:",
+        )?;
+        let last_name = match &self.credentials.last_name {
+            Some(last_name) => last_name.clone(),
+            None if self.credentials.first_name.is_some() => String::new(),
+            None => ask_user("Enter your last name:")?,
+        };
+        Ok(functions::register_user(first_name, last_name, client_id)
+            .await
+            .inspect_err(metrics::tdlib_error)?)
+    }
+
+    async fn code(&self, prompt: &str) -> AlterResult<String> {
+        match &self.code_source {
+            CodeSource::Prompt => {
+                ensure_terminal("verification code")?;
+                ask_user(prompt)
+            }
+            CodeSource::File(path) => {
+                info!("Waiting for the code in '{}'", path.display());
+                read_code_file(path).await
+            }
+            CodeSource::Http(address) => {
+                info!("Waiting for the code on http://{address}");
+                receive_code(*address).await
+            }
+        }
+    }
+}
+
+/// Shows the link to open on a device where I am logged in, for instance as a QR code.
+pub fn wait_other_device_confirmation(link: &str) {
+    info!("Confirm the login on another device, through this link: {link}");
+    println!("{link}");
+}
+
+fn configured_or_ask(
+    configured: &Option<String>,
+    name: &'static str,
+    prompt: &str,
+) -> AlterResult<String> {
+    match configured {
+        Some(value) => Ok(value.clone()),
+        None => {
+            ensure_terminal(name)?;
+            ask_user(prompt)
+        }
+    }
+}
+
+// Prompting without a terminal would fail with an obscure error
+fn ensure_terminal(name: &'static str) -> AlterResult<()> {
+    if std::io::stdin().is_terminal() {
+        Ok(())
+    } else {
+        Err(Error::MissingCredential(name))
+    }
+}
+
+fn ask_user(prompt: &str) -> AlterResult<String> {
+    Ok(Input::<String>::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .interact_text()?
+        .trim()
+        .into())
+}
+
+async fn read_code_file(path: &Path) -> AlterResult<String> {
+    loop {
+        match fs::metadata(path) {
+            Ok(metadata) if metadata.file_type().is_fifo() => {
+                // Blocks until something is written to the pipe
+                let path = path.to_owned();
+                let code = tokio::task::spawn_blocking(move || fs::read_to_string(path)).await??;
+                if !code.trim().is_empty() {
+                    return Ok(code.trim().into());
+                }
+            }
+            Ok(_) => {
+                let code = fs::read_to_string(path)?;
+                if !code.trim().is_empty() {
+                    // A code is only valid once
+                    fs::remove_file(path)?;
+                    return Ok(code.trim().into());
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        tokio::time::sleep(CODE_FILE_POLL).await;
+    }
+}
+
+async fn receive_code(address: SocketAddr) -> AlterResult<String> {
+    let listener = TcpListener::bind(address).await?;
+    let (code_tx, mut code_rx) = mpsc::channel(1);
+    let app = Router::new()
+        .fallback(post_code)
+        .with_state(code_tx)
+        .into_make_service_with_connect_info::<SocketAddr>();
+    let server = async { axum::serve(listener, app).await };
+    tokio::select! {
+        result = server => {
+            result?;
+            Err(Error::MissingCredential("verification code"))
+        }
+        Some(code) = code_rx.recv() => Ok(code),
+    }
+}
+
+#[derive(Deserialize)]
+struct CodeQuery {
+    code: Option<String>,
+}
+
+// The code is either the body of the request, or its `code` query parameter
+async fn post_code(
+    State(code_tx): State<mpsc::Sender<String>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Query(query): Query<CodeQuery>,
+    body: String,
+) -> (StatusCode, &'static str) {
+    let code = query
+        .code
+        .unwrap_or_else(|| body.trim().to_owned())
+        .trim()
+        .to_owned();
+    if code.is_empty() || !code.chars().all(|c| c.is_ascii_alphanumeric()) {
+        warn!("Request from {peer} without a code");
+        return (StatusCode::BAD_REQUEST, "Missing code\n");
+    }
+    info!("Received the code from {peer}");
+    // Only the first code is waited for
+    let _ = code_tx.try_send(code);
+    (StatusCode::OK, "OK\n")
+}
@@ -0,0 +1,180 @@
+use std::time::Duration;
+
+use log::{error, info, warn};
+use tdlib::{
+    enums::{self, ChatList},
+    functions,
+    types::Message,
+};
+
+use crate::{
+    database::Database,
+    error::{AlterResult, Error},
+    metrics, save,
+};
+
+const PAGE_SIZE: i32 = 100;
+const PAGE_DELAY: Duration = Duration::from_millis(500);
+const CHATS_PAGE_SIZE: i32 = 100;
+
+pub struct Progress {
+    pub chats: usize,
+    pub messages: usize,
+    /// Chats which could not be backfilled
+    pub failed: usize,
+}
+
+/// Pages through the history of the given chats, or of every private chat of the main list
+/// when none is given, going back from the oldest stored message of each chat. A chat which
+/// fails is left for the next ones, only a flood wait stops the backfill.
+pub async fn run(db: Database, chat_ids: Vec<i64>, client_id: i32) -> AlterResult<Progress> {
+    let chat_ids = if chat_ids.is_empty() {
+        private_chats(client_id).await?
+    } else {
+        chat_ids
+    };
+    info!("Backfilling {} chats", chat_ids.len());
+
+    let mut progress = Progress {
+        chats: 0,
+        messages: 0,
+        failed: 0,
+    };
+    for (index, chat_id) in chat_ids.iter().enumerate() {
+        match backfill_chat(&db, *chat_id, client_id).await {
+            Ok(messages) => {
+                progress.chats += 1;
+                progress.messages += messages;
+                info!(
+                    "[{chat_id}] Backfilled {messages} messages ({}/{} chats)",
+                    index + 1,
+                    chat_ids.len()
+                );
+            }
+            Err(Error::Tdlib(e)) if flood_wait(&e).is_some() => return Err(Error::Tdlib(e)),
+            Err(e) => {
+                progress.failed += 1;
+                error!(
+                    "[{chat_id}] Could not backfill ({}/{} chats): {e}",
+                    index + 1,
+                    chat_ids.len()
+                );
+            }
+        }
+    }
+    info!(
+        "Backfilled {} messages from {} chats, {} failed",
+        progress.messages, progress.chats, progress.failed
+    );
+    Ok(progress)
+}
+
+/// TDLib only knows the chats it was asked to load, so the main list is loaded up to its
+/// end before picking its private chats.
+async fn private_chats(client_id: i32) -> AlterResult<Vec<i64>> {
+    loop {
+        match functions::load_chats(Some(ChatList::Main), CHATS_PAGE_SIZE, client_id)
+            .await
+            .inspect_err(metrics::tdlib_error)
+        {
+            Ok(()) => (),
+            // Every chat of the list is loaded
+            Err(e) if e.code == 404 => break,
+            Err(e) => match flood_wait(&e) {
+                Some(retry_after) => {
+                    warn!("Flood wait while loading chats, retrying in {retry_after} s");
+                    tokio::time::sleep(Duration::from_secs(retry_after)).await;
+                }
+                None => return Err(e.into()),
+            },
+        }
+    }
+    let enums::Chats::Chats(chats) =
+        functions::get_chats(Some(ChatList::Main), i32::MAX, client_id)
+            .await
+            .inspect_err(metrics::tdlib_error)?;
+
+    let mut private_chats = Vec::new();
+    for chat_id in chats.chat_ids {
+        let enums::Chat::Chat(chat) = functions::get_chat(chat_id, client_id)
+            .await
+            .inspect_err(metrics::tdlib_error)?;
+        if matches!(chat.r#type, enums::ChatType::Private(_)) {
+            private_chats.push(chat_id);
+        }
+    }
+    Ok(private_chats)
+}
+
+async fn backfill_chat(db: &Database, chat_id: i64, client_id: i32) -> AlterResult<usize> {
+    // Loads the chat when TDLib does not know it yet, as its history is unavailable until then
+    functions::get_chat(chat_id, client_id)
+        .await
+        .inspect_err(metrics::tdlib_error)?;
+    let mut from_message_id = oldest_message_id(db, chat_id).await?.unwrap_or(0);
+    let mut backfilled = 0;
+    loop {
+        // The page starts with `from_message_id` itself, which is already stored
+        let messages = get_chat_history(chat_id, from_message_id, client_id)
+            .await?
+            .into_iter()
+            .filter(|message| message.id != from_message_id)
+            .collect::<Vec<_>>();
+        let Some(oldest) = messages.iter().map(|message| message.id).min() else {
+            return Ok(backfilled);
+        };
+        backfilled += messages.len();
+        for message in messages {
+            save::save_message(db, message)?;
+        }
+        from_message_id = oldest;
+        tokio::time::sleep(PAGE_DELAY).await;
+    }
+}
+
+async fn oldest_message_id(db: &Database, chat_id: i64) -> AlterResult<Option<i64>> {
+    db.execute(move |conn| {
+        Ok(conn.query_row(
+            r#"SELECT MIN(id) FROM MESSAGES WHERE chat_id = ?1"#,
+            rusqlite::params![chat_id],
+            |row| row.get(0),
+        )?)
+    })
+    .await
+}
+
+async fn get_chat_history(
+    chat_id: i64,
+    from_message_id: i64,
+    client_id: i32,
+) -> AlterResult<Vec<Message>> {
+    loop {
+        match functions::get_chat_history(chat_id, from_message_id, 0, PAGE_SIZE, false, client_id)
+            .await
+            .inspect_err(metrics::tdlib_error)
+        {
+            Ok(enums::Messages::Messages(messages)) => {
+                return Ok(messages.messages.into_iter().flatten().collect())
+            }
+            Err(e) => match flood_wait(&e) {
+                Some(retry_after) => {
+                    warn!("[{chat_id}] Flood wait, retrying in {retry_after} s");
+                    tokio::time::sleep(Duration::from_secs(retry_after)).await;
+                }
+                None => return Err(e.into()),
+            },
+        }
+    }
+}
+
+// Flood errors look like "Too Many Requests: retry after 42"
+fn flood_wait(error: &tdlib::types::Error) -> Option<u64> {
+    if error.code != 429 {
+        return None;
+    }
+    error
+        .message
+        .rsplit(' ')
+        .next()
+        .and_then(|seconds| seconds.parse().ok())
+}
@@ -0,0 +1,86 @@
+use std::time::Instant;
+
+use log::debug;
+use tdlib::types::Message;
+
+use crate::{
+    ai::{self, Control},
+    backfill,
+    database::Database,
+    error::AlterResult,
+    models::message_provenance::{Approval, MessageProvenance},
+    search, utils,
+};
+
+const SEARCH_LIMIT: usize = 10;
+
+/// Handles the `/command argument` messages written to Saved Messages, answering there.
+pub async fn handle(
+    db: Database,
+    control: &Control,
+    message: Message,
+    client_id: i32,
+) -> AlterResult<()> {
+    let Some(text) = utils::message_text(&message) else {
+        return Ok(());
+    };
+    let Some((command, argument)) = text
+        .strip_prefix('/')
+        .map(|text| text.split_once(' ').unwrap_or((text, "")))
+    else {
+        return Ok(());
+    };
+    let start = Instant::now();
+    let answer = match command {
+        "search" => search(&db, argument.trim()).await?,
+        "backfill" => backfill(&db, argument, client_id).await?,
+        _ => {
+            debug!("Ignoring unknown command '/{command}'");
+            return Ok(());
+        }
+    };
+    let sent = ai::send_message(message, answer, client_id).await?;
+    // Commands are explicit requests of mine
+    control.record_provenance(
+        &db,
+        MessageProvenance {
+            chat_id: sent.chat_id,
+            message_id: sent.id,
+            model: format!("/{command}"),
+            prompt_hash: utils::hash(&text),
+            latency_ms: start.elapsed().as_millis() as i64,
+            approval: Approval::Approved,
+            created_at: utils::unix_time(),
+        },
+    )
+}
+
+async fn backfill(db: &Database, argument: &str, client_id: i32) -> AlterResult<String> {
+    let Ok(chat_ids) = argument
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<Vec<i64>, _>>()
+    else {
+        return Ok("Usage: /backfill [chat_id...]".into());
+    };
+    let progress = backfill::run(db.clone(), chat_ids, client_id).await?;
+    Ok(format!(
+        "Backfilled {} messages from {} chats, {} failed",
+        progress.messages, progress.chats, progress.failed
+    ))
+}
+
+async fn search(db: &Database, query: &str) -> AlterResult<String> {
+    if query.is_empty() {
+        return Ok("Usage: /search <query>".into());
+    }
+    let hits = search::search(db, query, SEARCH_LIMIT).await?;
+    if hits.is_empty() {
+        return Ok(format!("No message matches '{query}'"));
+    }
+    Ok(hits
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
@@ -0,0 +1,705 @@
+use std::{
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, OnceLock},
+};
+
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{signal::unix, sync::watch};
+use tracing_subscriber::{
+    filter::LevelFilter, fmt::format::FmtSpan, layer::SubscriberExt, reload,
+    util::SubscriberInitExt, EnvFilter, Layer, Registry,
+};
+
+use crate::{
+    args::Args,
+    error::{AlterResult, Error},
+};
+
+const DEFAULT_PATH: &str = "alterego.toml";
+const ENV_PREFIX: &str = "ALTEREGO_";
+const DEFAULT_ACCOUNT: &str = "default";
+const MIN_TOKEN_LENGTH: usize = 16;
+const REDACTED: &str = "<redacted>";
+
+// Changes the log level on reload, unless RUST_LOG is set
+static LOG_LEVEL: OnceLock<reload::Handle<LevelFilter, Registry>> = OnceLock::new();
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub telegram: TelegramConfig,
+    pub database: DatabaseConfig,
+    pub ollama: OllamaConfig,
+    pub ai: AiConfig,
+    pub style: StyleConfig,
+    pub audit: AuditConfig,
+    pub log: LogConfig,
+    pub queues: QueueConfig,
+    pub metrics: MetricsConfig,
+    pub dashboard: DashboardConfig,
+    pub accounts: Vec<AccountConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TelegramConfig {
+    pub database_directory: String,
+    pub system_language_code: String,
+    pub device_model: String,
+    /// Verbosity of the TDLib logs, from 0 to 1023
+    pub log_verbosity: i32,
+}
+
+impl Default for TelegramConfig {
+    fn default() -> Self {
+        Self {
+            database_directory: "db_me".into(),
+            system_language_code: "en".into(),
+            device_model: "Desktop".into(),
+            log_verbosity: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DatabaseConfig {
+    pub path: PathBuf,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            path: "db.sqlite".into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OllamaConfig {
+    pub url: String,
+    /// Model used in the chats without a model of their own
+    pub model: String,
+}
+
+impl Default for OllamaConfig {
+    fn default() -> Self {
+        Self {
+            url: "http://localhost:11434".into(),
+            model: "alter-mistral".into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AiConfig {
+    /// Stands for the messages without text
+    pub fallback_message: String,
+    pub reading_wpm_min: f64,
+    pub reading_wpm_max: f64,
+    pub thinking_wpm_min: f64,
+    pub thinking_wpm_max: f64,
+    pub typing_wpm_min: f64,
+    pub typing_wpm_max: f64,
+    /// Milliseconds between two "typing" notifications
+    pub typing_action_interval_ms: u64,
+    /// Minutes without auto-replies in a chat after I type or write in it myself
+    pub takeover_pause_minutes: u64,
+    /// Most recent messages of a private chat given to the model
+    pub history_messages: usize,
+}
+
+impl Default for AiConfig {
+    fn default() -> Self {
+        Self {
+            fallback_message: "Salut".into(),
+            reading_wpm_min: 180.,
+            reading_wpm_max: 250.,
+            thinking_wpm_min: 1000.,
+            thinking_wpm_max: 3000.,
+            typing_wpm_min: 80.,
+            typing_wpm_max: 180.,
+            typing_action_interval_ms: 5000,
+            takeover_pause_minutes: 30,
+            history_messages: 50,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StyleConfig {
+    /// Minutes between two computations of the style profiles
+    pub refresh_minutes: u64,
+}
+
+impl Default for StyleConfig {
+    fn default() -> Self {
+        Self {
+            refresh_minutes: 360,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    /// Record the prompts and completions of every answer
+    pub enabled: bool,
+    /// Days after which the records are deleted
+    pub retention_days: u64,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            retention_days: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    /// Ignored when RUST_LOG is set
+    pub level: String,
+    /// Only read at startup
+    pub format: LogFormat,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "error".into(),
+            format: LogFormat::Text,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    /// One JSON object per line, with the fields of the current spans
+    Json,
+}
+
+/// An alter ego of its own, with its own Telegram account and database.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AccountConfig {
+    pub name: String,
+    pub database_directory: String,
+    pub database_path: PathBuf,
+    /// Defaults to `ollama.model`
+    pub model: Option<String>,
+    /// Who the alter ego is, prepended to the system prompt
+    pub persona: Option<String>,
+    /// Credentials to log in with, as given to `--auth-file`
+    pub auth_file: Option<PathBuf>,
+}
+
+impl AccountConfig {
+    pub fn model<'a>(&'a self, config: &'a Config) -> &'a str {
+        self.model.as_deref().unwrap_or(&config.ollama.model)
+    }
+}
+
+/// Capacities of the queues, only read at startup.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueueConfig {
+    /// Updates received from TDLib, before they are routed to their account
+    pub receive_capacity: usize,
+    /// Updates of an account waiting to be saved
+    pub updates_capacity: usize,
+    /// Messages of an account waiting to be answered
+    pub messages_capacity: usize,
+}
+
+/// The metrics endpoint, only read at startup.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// Local address to serve the Prometheus metrics on `/metrics`, not served when unset
+    pub listen: Option<SocketAddr>,
+}
+
+/// The web dashboard and its API, only read at startup.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DashboardConfig {
+    /// Local address to serve the dashboard on, not served when unset
+    pub listen: Option<SocketAddr>,
+    /// Expected as a bearer token, or given once to `/login?token=` to get a cookie
+    pub token: Option<String>,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            receive_capacity: 1024,
+            updates_capacity: 1024,
+            messages_capacity: 256,
+        }
+    }
+}
+
+impl Config {
+    /// The configured accounts, or a single one made of the `[telegram]` and `[database]`
+    /// settings when there is none.
+    pub fn accounts(&self) -> Vec<AccountConfig> {
+        if !self.accounts.is_empty() {
+            return self.accounts.clone();
+        }
+        vec![AccountConfig {
+            name: DEFAULT_ACCOUNT.into(),
+            database_directory: self.telegram.database_directory.clone(),
+            database_path: self.database.path.clone(),
+            model: None,
+            persona: None,
+            auth_file: None,
+        }]
+    }
+
+    pub fn account(&self, name: &str) -> Option<AccountConfig> {
+        self.accounts()
+            .into_iter()
+            .find(|account| account.name == name)
+    }
+
+    /// Layers the configuration file, the `ALTEREGO_<SECTION>_<KEY>` environment variables
+    /// and the command line over the defaults, then validates the result.
+    pub fn load(args: &Args) -> AlterResult<Self> {
+        let file = match path(args) {
+            Some(path) => Some((
+                toml::from_str(&fs::read_to_string(&path)?)?,
+                path.display().to_string(),
+            )),
+            None => None,
+        };
+        Self::layer(file, std::env::vars(), args)
+    }
+
+    // The file comes with where it was read from, to point at its unknown keys
+    fn layer(
+        file: Option<(Value, String)>,
+        variables: impl IntoIterator<Item = (String, String)>,
+        args: &Args,
+    ) -> AlterResult<Self> {
+        let mut layered = serde_json::to_value(Config::default())?;
+        if let Some((file, origin)) = file {
+            merge(&mut layered, file, &origin)?;
+        }
+        for (variable, value) in variables {
+            if let Some(key) = variable.strip_prefix(ENV_PREFIX) {
+                merge_env(&mut layered, &variable, &key.to_lowercase(), value)?;
+            }
+        }
+        let mut config: Config = serde_json::from_value(layered)?;
+
+        if let Some(database_directory) = &args.tg_database_directory {
+            config.telegram.database_directory = database_directory.clone();
+        }
+        if let Some(database_path) = &args.database_path {
+            config.database.path = database_path.clone();
+        }
+        if let Some(model_name) = &args.model_name {
+            config.ollama.model = model_name.clone();
+        }
+        if let Some(style_refresh_minutes) = args.style_refresh_minutes {
+            config.style.refresh_minutes = style_refresh_minutes;
+        }
+        if let Some(takeover_pause_minutes) = args.takeover_pause_minutes {
+            config.ai.takeover_pause_minutes = takeover_pause_minutes;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> AlterResult<()> {
+        let mut problems = Vec::new();
+        if self.telegram.database_directory.is_empty() {
+            problems.push("telegram.database_directory is empty".to_owned());
+        }
+        if !(0..=1023).contains(&self.telegram.log_verbosity) {
+            problems.push("telegram.log_verbosity must be between 0 and 1023".to_owned());
+        }
+        if !self.ollama.url.starts_with("http://") && !self.ollama.url.starts_with("https://") {
+            problems.push(format!(
+                "ollama.url '{}' is not an HTTP URL",
+                self.ollama.url
+            ));
+        }
+        if self.ollama.model.is_empty() {
+            problems.push("ollama.model is empty".to_owned());
+        }
+        for (name, min, max) in [
+            ("reading", self.ai.reading_wpm_min, self.ai.reading_wpm_max),
+            (
+                "thinking",
+                self.ai.thinking_wpm_min,
+                self.ai.thinking_wpm_max,
+            ),
+            ("typing", self.ai.typing_wpm_min, self.ai.typing_wpm_max),
+        ] {
+            if !(min > 0. && min <= max) {
+                problems.push(format!(
+                    "ai.{name}_wpm_min must be positive and at most ai.{name}_wpm_max"
+                ));
+            }
+        }
+        if self.ai.typing_action_interval_ms == 0 {
+            problems.push("ai.typing_action_interval_ms must be positive".to_owned());
+        }
+        if self.ai.history_messages == 0 {
+            problems.push("ai.history_messages must be positive".to_owned());
+        }
+        if self.style.refresh_minutes == 0 {
+            problems.push("style.refresh_minutes must be positive".to_owned());
+        }
+        if self.audit.retention_days == 0 {
+            problems.push("audit.retention_days must be positive".to_owned());
+        }
+        if let Some(listen) = self.metrics.listen {
+            if !listen.ip().is_loopback() {
+                problems.push(format!("metrics.listen '{listen}' is not a local address"));
+            }
+        }
+        if let Some(listen) = self.dashboard.listen {
+            if !listen.ip().is_loopback() {
+                problems.push(format!(
+                    "dashboard.listen '{listen}' is not a local address"
+                ));
+            }
+            if self
+                .dashboard
+                .token
+                .as_ref()
+                .is_none_or(|token| token.len() < MIN_TOKEN_LENGTH)
+            {
+                problems.push(format!(
+                    "dashboard.token must be at least {MIN_TOKEN_LENGTH} characters long"
+                ));
+            }
+        }
+        for (name, capacity) in [
+            ("receive_capacity", self.queues.receive_capacity),
+            ("updates_capacity", self.queues.updates_capacity),
+            ("messages_capacity", self.queues.messages_capacity),
+        ] {
+            if capacity == 0 {
+                problems.push(format!("queues.{name} must be positive"));
+            }
+        }
+        let accounts = self.accounts();
+        for (index, account) in accounts.iter().enumerate() {
+            if account.name.is_empty() {
+                problems.push(format!("accounts[{index}].name is empty"));
+            }
+            for other in &accounts[..index] {
+                if account.name == other.name {
+                    problems.push(format!("account name '{}' is used twice", account.name));
+                }
+                if account.database_directory == other.database_directory {
+                    problems.push(format!(
+                        "accounts '{}' and '{}' share their database directory",
+                        other.name, account.name
+                    ));
+                }
+                if account.database_path == other.database_path {
+                    problems.push(format!(
+                        "accounts '{}' and '{}' share their database",
+                        other.name, account.name
+                    ));
+                }
+            }
+        }
+        if LevelFilter::from_str(&self.log.level).is_err() {
+            problems.push(format!("log.level '{}' is not a log level", self.log.level));
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Config(problems.join(", ")))
+        }
+    }
+
+    pub fn log_level(&self) -> LevelFilter {
+        LevelFilter::from_str(&self.log.level).unwrap_or(LevelFilter::ERROR)
+    }
+}
+
+// The file given on the command line, or the default one when it exists
+fn path(args: &Args) -> Option<PathBuf> {
+    args.config.clone().or_else(|| {
+        Path::new(DEFAULT_PATH)
+            .exists()
+            .then(|| DEFAULT_PATH.into())
+    })
+}
+
+// Only the keys of the defaults are known, which catches typos
+fn merge(layered: &mut Value, layer: Value, origin: &str) -> AlterResult<()> {
+    let (Value::Object(layered), Value::Object(layer)) = (layered, layer) else {
+        return Err(Error::Config(format!("{origin}: expected a table")));
+    };
+    for (key, value) in layer {
+        match layered.get_mut(&key) {
+            Some(section @ Value::Object(_)) => merge(section, value, &format!("{origin}: {key}"))?,
+            Some(known) => *known = value,
+            None => return Err(Error::Config(format!("{origin}: unknown key '{key}'"))),
+        }
+    }
+    Ok(())
+}
+
+// `ai_takeover_pause_minutes` sets `takeover_pause_minutes` in `[ai]`, variables not
+// starting with a section are left to others
+fn merge_env(layered: &mut Value, variable: &str, key: &str, value: String) -> AlterResult<()> {
+    let Value::Object(sections) = layered else {
+        return Ok(());
+    };
+    for (section, keys) in sections.iter_mut() {
+        let Some(key) = key.strip_prefix(&format!("{section}_")) else {
+            continue;
+        };
+        let Some(known) = keys.get_mut(key) else {
+            return Err(Error::Config(format!(
+                "{variable}: unknown key '{key}' in [{section}]"
+            )));
+        };
+        let invalid = || Error::Config(format!("{section}.{key}: invalid value '{value}'"));
+        *known = match known {
+            Value::Bool(_) => Value::Bool(value.parse().map_err(|_| invalid())?),
+            Value::Number(number) if number.is_f64() => {
+                serde_json::json!(value.parse::<f64>().map_err(|_| invalid())?)
+            }
+            Value::Number(_) => serde_json::json!(value.parse::<i64>().map_err(|_| invalid())?),
+            _ => Value::String(value),
+        };
+        return Ok(());
+    }
+    Ok(())
+}
+
+/// Logs at the configured level unless RUST_LOG says otherwise, the `log` records included.
+/// Spans are logged when they close, with their duration.
+pub fn init_logger(config: &Config) {
+    let (level, env_filter) = if std::env::var_os("RUST_LOG").is_some() {
+        (None, Some(EnvFilter::from_default_env()))
+    } else {
+        let (level, handle) = reload::Layer::new(config.log_level());
+        let _ = LOG_LEVEL.set(handle);
+        (Some(level), None)
+    };
+    let output = match config.log.format {
+        LogFormat::Text => tracing_subscriber::fmt::layer()
+            .with_span_events(FmtSpan::CLOSE)
+            .boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .with_span_events(FmtSpan::CLOSE)
+            .boxed(),
+    };
+    tracing_subscriber::registry()
+        .with(level)
+        .with(env_filter)
+        .with(output)
+        .init();
+}
+
+/// Reloads the configuration on SIGHUP. The Telegram, database, queue, metrics and dashboard
+/// settings, and the list of accounts, are only read at startup, changing them requires a
+/// restart.
+pub fn watch(args: Args, config: Config) -> AlterResult<watch::Receiver<Arc<Config>>> {
+    let mut sighup = unix::signal(unix::SignalKind::hangup())?;
+    let (config_tx, config_rx) = watch::channel(Arc::new(config));
+    tokio::spawn(async move {
+        while sighup.recv().await.is_some() {
+            info!("Reloading the configuration");
+            let mut config = match Config::load(&args) {
+                Ok(config) => config,
+                Err(e) => {
+                    error!("Keeping the current configuration: {e:?}");
+                    continue;
+                }
+            };
+            let current = config_tx.borrow().clone();
+            if config.telegram != current.telegram
+                || config.database != current.database
+                || config.queues != current.queues
+                || config.metrics != current.metrics
+                || config.dashboard != current.dashboard
+            {
+                warn!(
+                    "The Telegram, database, queue, metrics and dashboard settings only apply \
+                    after a restart"
+                );
+                config.telegram = current.telegram.clone();
+                config.database = current.database.clone();
+                config.queues = current.queues.clone();
+                config.metrics = current.metrics.clone();
+                config.dashboard = current.dashboard.clone();
+            }
+            let identities = |config: &Config| {
+                config
+                    .accounts()
+                    .into_iter()
+                    .map(|account| {
+                        (
+                            account.name,
+                            account.database_directory,
+                            account.database_path,
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            };
+            if identities(&config) != identities(&current) {
+                warn!("Adding, removing or moving accounts only applies after a restart");
+                config.accounts = current.accounts.clone();
+            }
+            if let Some(handle) = LOG_LEVEL.get() {
+                if let Err(e) = handle.reload(config.log_level()) {
+                    error!("{e:#?}");
+                }
+            }
+            config_tx.send_replace(Arc::new(config));
+        }
+    });
+    Ok(config_rx)
+}
+
+/// Prints the configuration, loaded and validated, with every layer applied and its
+/// secrets hidden.
+pub fn check(config: &Config) -> AlterResult<()> {
+    let mut config = config.clone();
+    if config.dashboard.token.is_some() {
+        config.dashboard.token = Some(REDACTED.into());
+    }
+    println!("{}", toml::to_string_pretty(&config)?);
+    info!("Configuration is valid");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use serde_json::json;
+
+    use super::*;
+
+    fn layer(
+        file: Option<Value>,
+        variables: &[(&str, &str)],
+        args: &[&str],
+    ) -> AlterResult<Config> {
+        Config::layer(
+            file.map(|file| (file, "alterego.toml".to_owned())),
+            variables
+                .iter()
+                .map(|(variable, value)| (variable.to_string(), value.to_string())),
+            &Args::parse_from(["alterego"].iter().chain(args)),
+        )
+    }
+
+    fn file() -> Value {
+        json!({
+            "ollama": { "model": "file" },
+            "ai": { "takeover_pause_minutes": 10, "typing_wpm_max": 150.0 },
+            "audit": { "enabled": false },
+        })
+    }
+
+    fn assert_rejected(config: AlterResult<Config>, problem: &str) {
+        match config {
+            Err(Error::Config(message)) => assert!(message.contains(problem), "{message}"),
+            other => panic!("{problem} was accepted: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn defaults_without_any_layer() {
+        assert_eq!(layer(None, &[], &[]).unwrap(), Config::default());
+    }
+
+    #[test]
+    fn file_overrides_the_defaults() {
+        let config = layer(Some(file()), &[], &[]).unwrap();
+        assert_eq!(config.ollama.model, "file");
+        assert_eq!(config.ai.takeover_pause_minutes, 10);
+        assert_eq!(config.ai.typing_wpm_max, 150.);
+        assert_eq!(
+            config.ai.history_messages,
+            AiConfig::default().history_messages
+        );
+    }
+
+    #[test]
+    fn environment_overrides_the_file() {
+        let config = layer(
+            Some(file()),
+            &[
+                ("ALTEREGO_OLLAMA_MODEL", "env"),
+                ("ALTEREGO_AI_TAKEOVER_PAUSE_MINUTES", "20"),
+                ("ALTEREGO_AI_TYPING_WPM_MAX", "160.5"),
+                ("ALTEREGO_AUDIT_ENABLED", "true"),
+                // Read by the authentication, not a setting
+                ("ALTEREGO_PHONE_NUMBER", "+33612345678"),
+                ("HOME", "/root"),
+            ],
+            &[],
+        )
+        .unwrap();
+        assert_eq!(config.ollama.model, "env");
+        assert_eq!(config.ai.takeover_pause_minutes, 20);
+        assert_eq!(config.ai.typing_wpm_max, 160.5);
+        assert!(config.audit.enabled);
+    }
+
+    #[test]
+    fn command_line_overrides_the_environment() {
+        let config = layer(
+            Some(file()),
+            &[
+                ("ALTEREGO_OLLAMA_MODEL", "env"),
+                ("ALTEREGO_AI_TAKEOVER_PAUSE_MINUTES", "20"),
+            ],
+            &["--model-name", "cli", "--takeover-pause-minutes", "40"],
+        )
+        .unwrap();
+        assert_eq!(config.ollama.model, "cli");
+        assert_eq!(config.ai.takeover_pause_minutes, 40);
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_sections() {
+        assert_rejected(
+            layer(Some(json!({ "ai": { "takeover_pause": 10 } })), &[], &[]),
+            "unknown key 'takeover_pause'",
+        );
+        assert_rejected(
+            layer(Some(json!({ "llm": { "model": "mistral" } })), &[], &[]),
+            "unknown key 'llm'",
+        );
+        assert_rejected(
+            layer(None, &[("ALTEREGO_AI_TAKEOVER_PAUSE", "10")], &[]),
+            "unknown key 'takeover_pause' in [ai]",
+        );
+        assert_rejected(
+            layer(None, &[("ALTEREGO_AI_TAKEOVER_PAUSE_MINUTES", "soon")], &[]),
+            "invalid value 'soon'",
+        );
+    }
+}
@@ -0,0 +1,517 @@
+use std::{collections::HashMap, fmt::Write, sync::Arc};
+
+use axum::{
+    extract::{Path, Query, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Form, Router,
+};
+use log::{error, info};
+use serde::Deserialize;
+use tdlib::enums::{ChatType, MessageSender};
+use tokio::{net::TcpListener, sync::watch};
+
+use crate::{
+    ai::{Control, Event},
+    api,
+    config::Config,
+    database::Database,
+    error::{AlterResult, Error},
+    models::{
+        chat_llm_model::ChatLlmModel,
+        chat_reply_mode::{ChatReplyMode, ReplyMode},
+        chat_wrapper::ChatWrapper,
+        decode_rows,
+        message_wrapper::MessageWrapper,
+        setting, AutoRequestable,
+    },
+    utils,
+};
+
+const TOKEN_COOKIE: &str = "alterego_token";
+const RECENT_MESSAGES: i64 = 50;
+
+/// What the dashboard shows and changes for an account.
+pub struct DashboardAccount {
+    pub name: String,
+    pub db: Database,
+    pub control: Arc<Control>,
+}
+
+impl DashboardAccount {
+    /// The last messages of a chat older than the given message, oldest first. Message ids
+    /// grow with time within a chat, unlike dates which several messages can share.
+    pub async fn recent_messages(
+        &self,
+        chat_id: i64,
+        before_id: Option<i64>,
+        limit: i64,
+    ) -> AlterResult<Vec<MessageWrapper>> {
+        let mut messages = self
+            .db
+            .execute(move |conn| {
+                decode_rows::<MessageWrapper>(
+                    MessageWrapper::TABLE,
+                    conn.prepare(
+                        r#"SELECT rowid AS row_id, * FROM MESSAGES
+                    WHERE chat_id = :chat_id AND id < :before_id
+                    ORDER BY id DESC LIMIT :limit"#,
+                    )?
+                    .query(rusqlite::named_params! {
+                        ":chat_id": chat_id,
+                        ":before_id": before_id.unwrap_or(i64::MAX),
+                        ":limit": limit,
+                    })?,
+                )
+            })
+            .await?;
+        messages.reverse();
+        Ok(messages.into_iter().filter_map(Result::ok).collect())
+    }
+
+    pub async fn set_reply_mode(&self, chat_id: i64, mode: ReplyMode) -> AlterResult<()> {
+        self.db
+            .save_confirmed(ChatReplyMode {
+                chat_id,
+                mode,
+                updated_at: utils::unix_time(),
+            })
+            .await?;
+        match mode {
+            ReplyMode::Auto => self.control.resume(chat_id),
+            ReplyMode::Paused => self.control.interrupt(chat_id, "reply_mode").await,
+        }
+        self.control.publish(Event::ReplyMode {
+            chat_id,
+            mode: mode.as_str(),
+        });
+        info!(
+            "Reply mode of chat {chat_id} of {} set to {}",
+            self.name,
+            mode.as_str()
+        );
+        Ok(())
+    }
+
+    /// Without a model, the chat goes back to the model of the account.
+    pub async fn set_model(&self, chat_id: i64, model_name: Option<String>) -> AlterResult<()> {
+        match model_name.filter(|model_name| !model_name.trim().is_empty()) {
+            Some(model_name) => {
+                self.db
+                    .save_confirmed(ChatLlmModel::new(chat_id, model_name.trim().to_owned()))
+                    .await
+            }
+            None => {
+                self.db
+                    .write_confirmed(move |conn| ChatLlmModel::remove(conn, chat_id))
+                    .await
+            }
+        }
+    }
+
+    /// Without a persona, the account goes back to the configured one.
+    pub async fn set_persona(&self, persona: Option<String>) -> AlterResult<()> {
+        match persona {
+            Some(persona) => {
+                let persona = persona.trim().to_owned();
+                self.db
+                    .write_confirmed(move |conn| setting::set(conn, setting::PERSONA, &persona))
+                    .await
+            }
+            None => {
+                self.db
+                    .write_confirmed(|conn| setting::remove(conn, setting::PERSONA))
+                    .await
+            }
+        }
+    }
+}
+
+/// Shared by the dashboard pages and the API.
+pub struct DashboardState {
+    token: String,
+    accounts: Vec<DashboardAccount>,
+    config_rx: watch::Receiver<Arc<Config>>,
+}
+
+impl DashboardState {
+    pub fn accounts(&self) -> &[DashboardAccount] {
+        &self.accounts
+    }
+
+    pub fn config(&self) -> Arc<Config> {
+        self.config_rx.borrow().clone()
+    }
+
+    pub fn account(&self, name: &str) -> Result<&DashboardAccount, Failure> {
+        self.accounts
+            .iter()
+            .find(|account| account.name == name)
+            .ok_or(Failure::NotFound)
+    }
+}
+
+pub enum Failure {
+    NotFound,
+    BadRequest(String),
+    /// The account is not logged in yet
+    Unavailable,
+    Internal(Error),
+}
+
+impl From<Error> for Failure {
+    fn from(e: Error) -> Self {
+        Self::Internal(e)
+    }
+}
+
+impl IntoResponse for Failure {
+    fn into_response(self) -> Response {
+        match self {
+            Failure::NotFound => (StatusCode::NOT_FOUND, "Not found").into_response(),
+            Failure::BadRequest(reason) => (StatusCode::BAD_REQUEST, reason).into_response(),
+            Failure::Unavailable => {
+                (StatusCode::SERVICE_UNAVAILABLE, "Not logged in yet").into_response()
+            }
+            Failure::Internal(e) => {
+                error!("{e:#?}");
+                (StatusCode::INTERNAL_SERVER_ERROR, "Internal error").into_response()
+            }
+        }
+    }
+}
+
+/// Serves the dashboard of every account, to whoever has the token.
+pub async fn serve(
+    listener: TcpListener,
+    token: String,
+    accounts: Vec<DashboardAccount>,
+    config_rx: watch::Receiver<Arc<Config>>,
+) {
+    if let Ok(address) = listener.local_addr() {
+        info!("Serving the dashboard and its API on http://{address}");
+    }
+    let state = Arc::new(DashboardState {
+        token,
+        accounts,
+        config_rx,
+    });
+    let app = Router::new()
+        .route("/", get(index))
+        .route("/accounts/:account/chats/:chat_id", get(chat))
+        .route(
+            "/accounts/:account/chats/:chat_id/reply-mode",
+            post(set_reply_mode),
+        )
+        .route("/accounts/:account/chats/:chat_id/model", post(set_model))
+        .route("/accounts/:account/persona", post(set_persona))
+        .nest("/api", api::routes())
+        .route_layer(middleware::from_fn_with_state(state.clone(), authenticate))
+        .route("/login", get(login))
+        .with_state(state);
+    if let Err(e) = axum::serve(listener, app).await {
+        error!("{e:#?}");
+    }
+}
+
+async fn authenticate(
+    State(state): State<Arc<DashboardState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let headers = request.headers();
+    let token = bearer_token(headers).or_else(|| cookie(headers, TOKEN_COOKIE));
+    if token.is_some_and(|token| same_token(token, &state.token)) {
+        next.run(request).await
+    } else {
+        (StatusCode::UNAUTHORIZED, "Unauthorized").into_response()
+    }
+}
+
+#[derive(Deserialize)]
+struct LoginQuery {
+    token: String,
+}
+
+/// Trades the token for a cookie, so that the dashboard can be browsed.
+async fn login(
+    State(state): State<Arc<DashboardState>>,
+    Query(query): Query<LoginQuery>,
+) -> Response {
+    if !same_token(&query.token, &state.token) {
+        return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    }
+    (
+        [(
+            header::SET_COOKIE,
+            format!(
+                "{TOKEN_COOKIE}={}; HttpOnly; SameSite=Strict; Path=/",
+                query.token
+            ),
+        )],
+        Redirect::to("/"),
+    )
+        .into_response()
+}
+
+async fn index(State(state): State<Arc<DashboardState>>) -> Result<Html<String>, Failure> {
+    let config = state.config();
+    let mut body = String::new();
+    for account in &state.accounts {
+        let Some(account_config) = config.account(&account.name) else {
+            continue;
+        };
+        let default_model = account_config.model(&config);
+        let db = &account.db;
+        let models = db
+            .load_all::<ChatLlmModel>()
+            .await?
+            .into_iter()
+            .map(|llm| (llm.chat_id(), llm.model_name().to_owned()))
+            .collect::<HashMap<_, _>>();
+        let reply_modes = db
+            .load_all::<ChatReplyMode>()
+            .await?
+            .into_iter()
+            .map(|reply_mode| (reply_mode.chat_id, reply_mode.mode))
+            .collect::<HashMap<_, _>>();
+        let last_dates = db
+            .execute(|conn| {
+                let mut statement =
+                    conn.prepare(r#"SELECT chat_id, MAX(date) FROM MESSAGES GROUP BY chat_id"#)?;
+                let last_dates = statement
+                    .query_map(rusqlite::params![], |row| Ok((row.get(0)?, row.get(1)?)))?
+                    .collect::<Result<HashMap<i64, i64>, _>>()?;
+                Ok(last_dates)
+            })
+            .await?;
+        let mut chats = db
+            .scan::<ChatWrapper>()
+            .await?
+            .into_iter()
+            .filter_map(Result::ok)
+            .collect::<Vec<_>>();
+        chats.sort_by_key(|chat| std::cmp::Reverse(last_dates.get(&chat.id).copied()));
+
+        let name = escape(&account.name);
+        let _ = write!(body, "<h2>{name}</h2>");
+
+        let persona = match db
+            .execute(|conn| setting::get(conn, setting::PERSONA))
+            .await?
+        {
+            Some(persona) => persona,
+            None => account_config.persona.clone().unwrap_or_default(),
+        };
+        let _ = write!(
+            body,
+            r#"<form method="post" action="/accounts/{name}/persona">
+            <textarea name="persona" rows="4" cols="80">{}</textarea><br>
+            <button>Save the persona</button>
+            <button name="reset" value="true">Use the configured persona</button>
+            </form>"#,
+            escape(&persona)
+        );
+
+        let thoughts = account.control.thoughts();
+        if !thoughts.is_empty() {
+            body.push_str(
+                "<h3>Answering</h3><table><tr><th>Chat</th><th>Message</th><th>Since</th></tr>",
+            );
+            for thought in thoughts {
+                let _ = write!(
+                    body,
+                    "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    escape(&utils::chat_display_name(db, thought.chat_id).await),
+                    thought.message_id,
+                    utils::format_time(thought.started_at),
+                );
+            }
+            body.push_str("</table>");
+        }
+
+        body.push_str(
+            "<h3>Chats</h3><table><tr><th>Chat</th><th>Type</th><th>Last message</th>\
+            <th>Replies</th><th>Model</th></tr>",
+        );
+        for chat in chats {
+            let chat_type = match chat.r#type {
+                ChatType::Private(_) => "private",
+                ChatType::BasicGroup(_) => "group",
+                ChatType::Supergroup(_) => "supergroup",
+                ChatType::Secret(_) => "secret",
+            };
+            let last_date = last_dates
+                .get(&chat.id)
+                .map(|date| utils::format_time(*date))
+                .unwrap_or_default();
+            let reply_mode = reply_modes
+                .get(&chat.id)
+                .copied()
+                .unwrap_or(ReplyMode::Auto);
+            let taken_over = account
+                .control
+                .taken_over_for(chat.id)
+                .map(|left| format!(" (taken over, {} min left)", left.as_secs() / 60 + 1))
+                .unwrap_or_default();
+            let toggle = match reply_mode {
+                ReplyMode::Auto => ReplyMode::Paused,
+                ReplyMode::Paused => ReplyMode::Auto,
+            };
+            let chat_path = format!("/accounts/{name}/chats/{}", chat.id);
+            let _ = write!(
+                body,
+                r#"<tr><td><a href="{chat_path}">{}</a></td><td>{chat_type}</td><td>{last_date}</td>
+                <td><form method="post" action="{chat_path}/reply-mode">{}{taken_over}
+                <button name="mode" value="{}">{}</button></form></td>
+                <td><form method="post" action="{chat_path}/model">
+                <input name="model" value="{}" placeholder="{}"><button>Change</button></form></td>
+                </tr>"#,
+                escape(&chat.title),
+                reply_mode.as_str(),
+                toggle.as_str(),
+                match toggle {
+                    ReplyMode::Auto => "Resume",
+                    ReplyMode::Paused => "Pause",
+                },
+                escape(models.get(&chat.id).map_or("", String::as_str)),
+                escape(default_model),
+            );
+        }
+        body.push_str("</table>");
+    }
+    Ok(page("alterego", &body))
+}
+
+async fn chat(
+    State(state): State<Arc<DashboardState>>,
+    Path((account_name, chat_id)): Path<(String, i64)>,
+) -> Result<Html<String>, Failure> {
+    let account = state.account(&account_name)?;
+    let db = &account.db;
+    let messages = account
+        .recent_messages(chat_id, None, RECENT_MESSAGES)
+        .await?;
+
+    let title = utils::chat_display_name(db, chat_id).await;
+    let mut body = format!(r#"<p><a href="/">Back</a></p><h2>{}</h2>"#, escape(&title));
+    body.push_str("<table>");
+    for message in messages {
+        let sender = match &message.sender_id {
+            _ if message.is_outgoing => "Me".to_owned(),
+            MessageSender::User(user) => utils::user_display_name(db, user.user_id).await,
+            MessageSender::Chat(chat) => utils::chat_display_name(db, chat.chat_id).await,
+        };
+        let _ = write!(
+            body,
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            utils::format_time(message.date.into()),
+            escape(&sender),
+            escape(&utils::message_text(&message).unwrap_or_default()),
+        );
+    }
+    body.push_str("</table>");
+    Ok(page(&title, &body))
+}
+
+#[derive(Deserialize)]
+struct ReplyModeForm {
+    mode: String,
+}
+
+async fn set_reply_mode(
+    State(state): State<Arc<DashboardState>>,
+    Path((account_name, chat_id)): Path<(String, i64)>,
+    Form(form): Form<ReplyModeForm>,
+) -> Result<Redirect, Failure> {
+    let account = state.account(&account_name)?;
+    let mode = form
+        .mode
+        .parse::<ReplyMode>()
+        .map_err(Failure::BadRequest)?;
+    account.set_reply_mode(chat_id, mode).await?;
+    Ok(Redirect::to("/"))
+}
+
+#[derive(Deserialize)]
+struct ModelForm {
+    model: String,
+}
+
+async fn set_model(
+    State(state): State<Arc<DashboardState>>,
+    Path((account_name, chat_id)): Path<(String, i64)>,
+    Form(form): Form<ModelForm>,
+) -> Result<Redirect, Failure> {
+    state
+        .account(&account_name)?
+        .set_model(chat_id, Some(form.model))
+        .await?;
+    Ok(Redirect::to("/"))
+}
+
+#[derive(Deserialize)]
+struct PersonaForm {
+    persona: String,
+    /// Goes back to the persona of the configuration
+    reset: Option<String>,
+}
+
+async fn set_persona(
+    State(state): State<Arc<DashboardState>>,
+    Path(account_name): Path<String>,
+    Form(form): Form<PersonaForm>,
+) -> Result<Redirect, Failure> {
+    let persona = form.reset.is_none().then_some(form.persona);
+    state.account(&account_name)?.set_persona(persona).await?;
+    Ok(Redirect::to("/"))
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(cookie_name, _)| *cookie_name == name)
+        .map(|(_, value)| value)
+}
+
+// Compares every byte, so that the time taken does not tell how much of the token matched
+fn same_token(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn page(title: &str, body: &str) -> Html<String> {
+    Html(format!(
+        r#"<!DOCTYPE html>
+<html><head><meta charset="utf-8"><title>{}</title>
+<style>body {{ font-family: sans-serif; margin: 2em; }} td, th {{ padding: 0.2em 0.6em; text-align: left; vertical-align: top; }}</style>
+</head><body>{body}</body></html>"#,
+        escape(title)
+    ))
+}
@@ -1,55 +1,350 @@
-use std::path::Path;
+use std::{
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::Instant,
+};
 
-use log::debug;
-use rusqlite::Connection;
+use log::{debug, error, trace};
+use rusqlite::{Connection, OpenFlags};
+use tokio::sync::oneshot;
 
 use crate::{
-    error::AlterResult,
-    models::{init_db, AutoRequestable},
+    error::{AlterResult, Error},
+    metrics, migrations,
+    models::{AutoRequestable, RowError},
 };
 
-pub struct Database {
-    conn: Connection,
+const MAX_BATCH_SIZE: usize = 512;
+const MAX_IDLE_READERS: usize = 8;
+
+type Run = Box<dyn FnMut(&Connection) -> AlterResult<()> + Send>;
+
+struct Job {
+    // Runs again on its own when its batch can't be committed
+    run: Run,
+    // Receives the outcome of the job once committed, which is logged otherwise
+    done_tx: Option<oneshot::Sender<AlterResult<()>>>,
 }
 
-unsafe impl Send for Database {}
-unsafe impl Sync for Database {}
+enum Write {
+    Job(Job),
+    Flush(oneshot::Sender<()>),
+}
+
+struct Readers {
+    db_path: PathBuf,
+    idle: Mutex<Vec<Connection>>,
+}
+
+/// Writes are queued to a dedicated writer thread which commits them in batches, while
+/// reads run concurrently on a pool of read-only connections thanks to WAL mode.
+#[derive(Clone)]
+pub struct Database {
+    write_tx: mpsc::Sender<Write>,
+    readers: Arc<Readers>,
+}
 
 impl Database {
-    pub fn new(db_path: &Path) -> Result<Self, rusqlite::Error> {
+    pub fn new(db_path: &Path) -> AlterResult<Self> {
         debug!("Creating database '{}'", db_path.display());
-        let conn = Connection::open(db_path)?;
-        init_db(&conn)?;
-        Ok(Self { conn })
+        let mut conn = Connection::open(db_path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        migrations::run(&mut conn)?;
+
+        let (write_tx, write_rx) = mpsc::channel();
+        thread::Builder::new()
+            .name("database-writer".into())
+            .spawn(move || write_loop(conn, write_rx))?;
+        Ok(Self {
+            write_tx,
+            readers: Arc::new(Readers {
+                db_path: db_path.to_owned(),
+                idle: Mutex::new(Vec::new()),
+            }),
+        })
     }
 
-    pub fn save<DatabaseEntity: AutoRequestable + serde::Serialize>(
+    pub fn save<DatabaseEntity: AutoRequestable + Send + 'static>(
         &self,
-        entity: &DatabaseEntity,
+        entity: DatabaseEntity,
     ) -> AlterResult<()> {
-        if let Some(_) = DatabaseEntity::select_by_id(entity.get_id(), &self.conn)? {
-            entity.update(&self.conn)?;
-        } else {
-            entity.insert(&self.conn)?;
-        }
-        Ok(())
+        self.write(move |conn| entity.upsert(conn))
     }
 
-    pub fn load<DatabaseEntity: AutoRequestable<UniqueIdentifier = i64>>(
+    /// Saves an entity and waits until it is committed.
+    pub async fn save_confirmed<DatabaseEntity: AutoRequestable + Send + 'static>(
         &self,
-        id: i64,
-    ) -> AlterResult<Option<DatabaseEntity>> {
-        DatabaseEntity::select_by_id(id, &self.conn)
+        entity: DatabaseEntity,
+    ) -> AlterResult<()> {
+        self.write_confirmed(move |conn| entity.upsert(conn)).await
     }
 
-    pub fn load_all<DatabaseEntity: AutoRequestable>(&self) -> AlterResult<Vec<DatabaseEntity>> {
-        DatabaseEntity::select_all(&self.conn)
+    pub async fn load<DatabaseEntity: AutoRequestable + Send + 'static>(
+        &self,
+        id: DatabaseEntity::UniqueIdentifier,
+    ) -> AlterResult<Option<DatabaseEntity>>
+    where
+        DatabaseEntity::UniqueIdentifier: Send,
+    {
+        self.execute(|conn| DatabaseEntity::select_by_id(id, conn))
+            .await
     }
 
-    pub fn execute<DatabaseEntity: AutoRequestable>(
+    pub async fn load_all<DatabaseEntity: AutoRequestable + Send + 'static>(
         &self,
-        context: impl Fn(&Connection) -> AlterResult<Vec<DatabaseEntity>>,
     ) -> AlterResult<Vec<DatabaseEntity>> {
-        context(&self.conn)
+        self.execute(|conn| DatabaseEntity::select_all(conn)).await
+    }
+
+    pub async fn scan<DatabaseEntity: AutoRequestable + Send + 'static>(
+        &self,
+    ) -> AlterResult<Vec<Result<DatabaseEntity, RowError>>> {
+        self.execute(|conn| DatabaseEntity::scan(conn)).await
+    }
+
+    /// Queues a write and returns as soon as it is queued. It runs in its own savepoint, so
+    /// a failure is logged and rolled back without affecting the rest of its batch. The job
+    /// may run twice, when its batch fails to commit and is retried one write at a time.
+    pub fn write(
+        &self,
+        job: impl FnMut(&Connection) -> AlterResult<()> + Send + 'static,
+    ) -> AlterResult<()> {
+        self.queue(job, None)
+    }
+
+    /// Queues a write and waits until it is committed, returning its outcome.
+    pub async fn write_confirmed(
+        &self,
+        job: impl FnMut(&Connection) -> AlterResult<()> + Send + 'static,
+    ) -> AlterResult<()> {
+        let (done_tx, done_rx) = oneshot::channel();
+        self.queue(job, Some(done_tx))?;
+        done_rx.await.map_err(|_| Error::DatabaseClosed)?
+    }
+
+    fn queue(
+        &self,
+        job: impl FnMut(&Connection) -> AlterResult<()> + Send + 'static,
+        done_tx: Option<oneshot::Sender<AlterResult<()>>>,
+    ) -> AlterResult<()> {
+        self.write_tx
+            .send(Write::Job(Job {
+                run: Box::new(job),
+                done_tx,
+            }))
+            .map_err(|_| Error::DatabaseClosed)
+    }
+
+    /// Waits until every write queued so far is committed.
+    pub async fn flush(&self) -> AlterResult<()> {
+        let (flushed_tx, flushed_rx) = oneshot::channel();
+        self.write_tx
+            .send(Write::Flush(flushed_tx))
+            .map_err(|_| Error::DatabaseClosed)?;
+        flushed_rx.await.map_err(|_| Error::DatabaseClosed)
+    }
+
+    /// Runs read-only queries on a pooled connection, off the async runtime.
+    pub async fn execute<T: Send + 'static>(
+        &self,
+        context: impl FnOnce(&Connection) -> AlterResult<T> + Send + 'static,
+    ) -> AlterResult<T> {
+        let readers = self.readers.clone();
+        tokio::task::spawn_blocking(move || readers.run(context)).await?
+    }
+}
+
+impl Readers {
+    fn run<T>(&self, context: impl FnOnce(&Connection) -> AlterResult<T>) -> AlterResult<T> {
+        let idle = self.idle.lock().unwrap().pop();
+        let conn = match idle {
+            Some(conn) => conn,
+            None => Connection::open_with_flags(
+                &self.db_path,
+                OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+            )?,
+        };
+        let result = context(&conn);
+        let mut idle = self.idle.lock().unwrap();
+        if idle.len() < MAX_IDLE_READERS {
+            idle.push(conn);
+        }
+        result
+    }
+}
+
+fn write_loop(mut conn: Connection, write_rx: mpsc::Receiver<Write>) {
+    while let Ok(write) = write_rx.recv() {
+        let mut batch = vec![write];
+        while batch.len() < MAX_BATCH_SIZE {
+            match write_rx.try_recv() {
+                Ok(write) => batch.push(write),
+                Err(_) => break,
+            }
+        }
+        write_batch(&mut conn, batch);
+    }
+    debug!("Database writer stopped");
+}
+
+fn write_batch(conn: &mut Connection, batch: Vec<Write>) {
+    trace!("Writing a batch of {} operations", batch.len());
+    let mut jobs = Vec::new();
+    let mut flushed = Vec::new();
+    for write in batch {
+        match write {
+            Write::Job(job) => jobs.push(job),
+            Write::Flush(flushed_tx) => flushed.push(flushed_tx),
+        }
+    }
+
+    match run_jobs(conn, &mut jobs) {
+        Ok(results) => {
+            for (job, result) in jobs.into_iter().zip(results) {
+                report(job, result);
+            }
+        }
+        Err(e) => {
+            error!("Failed to commit a batch of writes, retrying them one by one: {e:#?}");
+            for mut job in jobs {
+                let result = run_jobs(conn, std::slice::from_mut(&mut job))
+                    .and_then(|mut results| results.pop().unwrap());
+                report(job, result);
+            }
+        }
+    }
+    for flushed_tx in flushed {
+        let _ = flushed_tx.send(());
+    }
+}
+
+/// Runs jobs in one transaction, each in its own savepoint. The outcome of each job is
+/// returned once the transaction is committed.
+fn run_jobs(conn: &mut Connection, jobs: &mut [Job]) -> AlterResult<Vec<AlterResult<()>>> {
+    let mut results = Vec::with_capacity(jobs.len());
+    let mut tx = conn.transaction()?;
+    for job in jobs {
+        let start = Instant::now();
+        let savepoint = tx.savepoint()?;
+        // A panicking job must not take the writer thread down with it
+        let result = panic::catch_unwind(AssertUnwindSafe(|| (job.run)(&savepoint)))
+            .unwrap_or(Err(Error::DatabaseJobPanicked));
+        if result.is_ok() {
+            savepoint.commit()?;
+        }
+        metrics::observe(metrics::DB_WRITE, &[], start.elapsed());
+        results.push(result);
+    }
+    tx.commit()?;
+    Ok(results)
+}
+
+fn report(job: Job, result: AlterResult<()>) {
+    match job.done_tx {
+        Some(done_tx) => {
+            let _ = done_tx.send(result);
+        }
+        None => {
+            if let Err(e) = result {
+                error!("{e:#?}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::*;
+
+    struct TestDatabase {
+        db: Database,
+        path: PathBuf,
+    }
+
+    impl TestDatabase {
+        fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("alterego-{name}-{}.sqlite", std::process::id()));
+            let _ = fs::remove_file(&path);
+            Self {
+                db: Database::new(&path).unwrap(),
+                path,
+            }
+        }
+    }
+
+    impl Drop for TestDatabase {
+        fn drop(&mut self) {
+            for suffix in ["", "-wal", "-shm"] {
+                let _ = fs::remove_file(format!("{}{suffix}", self.path.display()));
+            }
+        }
+    }
+
+    fn set(conn: &Connection, value: &str) -> AlterResult<()> {
+        crate::models::setting::set(conn, "test", value)
+    }
+
+    async fn get(db: &Database) -> Option<String> {
+        db.execute(|conn| crate::models::setting::get(conn, "test"))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn reports_the_outcome_of_confirmed_writes() {
+        let test = TestDatabase::new("confirmed");
+        test.db
+            .write_confirmed(|conn| set(conn, "a"))
+            .await
+            .unwrap();
+        assert_eq!(get(&test.db).await.as_deref(), Some("a"));
+        assert!(test
+            .db
+            .write_confirmed(|conn| Ok(conn.execute_batch("INSERT INTO NOWHERE VALUES (1)")?))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn survives_panicking_writes() {
+        let test = TestDatabase::new("panic");
+        test.db.write(|conn| set(conn, "a")).unwrap();
+        test.db.write(|_| panic!("failing write")).unwrap();
+        test.db.write(|conn| set(conn, "b")).unwrap();
+        test.db.flush().await.unwrap();
+        assert_eq!(get(&test.db).await.as_deref(), Some("b"));
+        assert!(matches!(
+            test.db.write_confirmed(|_| panic!("failing write")).await,
+            Err(Error::DatabaseJobPanicked)
+        ));
+        test.db
+            .write_confirmed(|conn| set(conn, "c"))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn retries_the_writes_of_an_uncommitted_batch() {
+        let test = TestDatabase::new("retry");
+        // Holds the writer so that the next writes are batched together
+        test.db
+            .write(|_| {
+                thread::sleep(std::time::Duration::from_millis(100));
+                Ok(())
+            })
+            .unwrap();
+        // Ends the transaction of the batch early, which then fails to commit
+        test.db
+            .write(|conn| Ok(conn.execute_batch("COMMIT")?))
+            .unwrap();
+        let committed = test.db.write_confirmed(|conn| set(conn, "a"));
+        assert!(committed.await.is_ok());
+        assert_eq!(get(&test.db).await.as_deref(), Some("a"));
     }
 }
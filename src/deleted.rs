@@ -0,0 +1,37 @@
+use tdlib::enums::MessageSender;
+
+use crate::{
+    database::Database,
+    error::AlterResult,
+    models::message_deletion::{DeletedMessage, MessageDeletion},
+    utils,
+};
+
+pub async fn run(db: &Database, chat_id: i64) -> AlterResult<()> {
+    let deleted_messages = db
+        .execute(move |conn| MessageDeletion::select_by_chat(chat_id, conn))
+        .await?;
+    for DeletedMessage { deletion, message } in &deleted_messages {
+        let content = match message {
+            Some(message) => {
+                let sender = match &message.sender_id {
+                    MessageSender::User(user) => user.user_id,
+                    MessageSender::Chat(chat) => chat.chat_id,
+                };
+                format!(
+                    "{sender}: {}",
+                    utils::message_text(message).unwrap_or_else(|| "(Not text)".into())
+                )
+            }
+            None => "(Not archived)".into(),
+        };
+        println!(
+            "[{}] {} {}: {content}",
+            deletion.deleted_at,
+            deletion.reason.as_str(),
+            deletion.message_id,
+        );
+    }
+    println!("{} deleted message(s)", deleted_messages.len());
+    Ok(())
+}
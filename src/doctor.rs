@@ -0,0 +1,61 @@
+use crate::{
+    database::Database,
+    error::AlterResult,
+    models::{
+        basic_group_wrapper::BasicGroupWrapper, chat_llm_model::ChatLlmModel,
+        chat_reply_mode::ChatReplyMode, chat_wrapper::ChatWrapper, decode_rows,
+        inference_audit::InferenceAudit, message_deletion::MessageDeletion,
+        message_provenance::MessageProvenance, message_wrapper::MessageWrapper,
+        style_profile::StyleProfile, supergroup_wrapper::SupergroupWrapper,
+        user_wrapper::UserWrapper, AutoRequestable, RowError,
+    },
+};
+
+pub async fn run(db: &Database) -> AlterResult<()> {
+    let mut undecodable = Vec::new();
+    undecodable.extend(scan::<BasicGroupWrapper>(db).await?);
+    undecodable.extend(scan::<ChatLlmModel>(db).await?);
+    undecodable.extend(scan::<ChatReplyMode>(db).await?);
+    undecodable.extend(scan::<InferenceAudit>(db).await?);
+    undecodable.extend(scan::<ChatWrapper>(db).await?);
+    undecodable.extend(scan::<MessageWrapper>(db).await?);
+    undecodable.extend(scan::<MessageDeletion>(db).await?);
+    undecodable.extend(scan::<MessageProvenance>(db).await?);
+    undecodable.extend(scan::<StyleProfile>(db).await?);
+    undecodable.extend(
+        db.execute(|conn| {
+            decode_rows::<MessageWrapper>(
+                "MESSAGES_ARCHIVE",
+                conn.prepare(r#"SELECT rowid AS row_id, * FROM MESSAGES_ARCHIVE"#)?
+                    .query(rusqlite::params![])?,
+            )
+        })
+        .await?
+        .into_iter()
+        .filter_map(Result::err),
+    );
+    undecodable.extend(scan::<SupergroupWrapper>(db).await?);
+    undecodable.extend(scan::<UserWrapper>(db).await?);
+
+    for RowError {
+        table,
+        row_id,
+        error,
+    } in &undecodable
+    {
+        println!("{table} row {row_id}: {error}");
+    }
+    println!("{} undecodable row(s)", undecodable.len());
+    Ok(())
+}
+
+async fn scan<DatabaseEntity: AutoRequestable + Send + 'static>(
+    db: &Database,
+) -> AlterResult<Vec<RowError>> {
+    Ok(db
+        .scan::<DatabaseEntity>()
+        .await?
+        .into_iter()
+        .filter_map(Result::err)
+        .collect())
+}
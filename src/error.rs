@@ -1,3 +1,7 @@
+use std::fmt;
+
+use crate::models::RowError;
+
 pub type AlterResult<T> = Result<T, Error>;
 
 #[derive(Debug)]
@@ -9,8 +13,59 @@ pub enum Error {
     Dialoguer(dialoguer::Error),
     Signal(tokio::sync::broadcast::error::SendError<()>),
     Reqwest(reqwest::Error),
+    /// The model failed to answer
+    Ollama(String),
+    Json(serde_json::Error),
+    RowDecode(RowError),
+    SchemaVersion {
+        found: i64,
+        supported: i64,
+    },
+    DatabaseClosed,
+    DatabaseJobPanicked,
+    MissingCredential(&'static str),
+    InvalidCredential(&'static str),
+    Config(String),
+    QueueClosed,
+    Toml(toml::de::Error),
+    TomlSerialize(toml::ser::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Tdlib(e) => write!(f, "TDLib error {}: {}", e.code, e.message),
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Database(e) => write!(f, "{e}"),
+            Error::Tokio(e) => write!(f, "{e}"),
+            Error::Dialoguer(e) => write!(f, "{e}"),
+            Error::Signal(e) => write!(f, "{e}"),
+            Error::Reqwest(e) => write!(f, "{e}"),
+            Error::Ollama(e) => write!(f, "Ollama error: {e}"),
+            Error::Json(e) => write!(f, "{e}"),
+            Error::RowDecode(RowError {
+                table,
+                row_id,
+                error,
+            }) => write!(f, "{table} row {row_id}: {error}"),
+            Error::SchemaVersion { found, supported } => write!(
+                f,
+                "the database schema version {found} is newer than the supported {supported}"
+            ),
+            Error::DatabaseClosed => write!(f, "the database is closed"),
+            Error::DatabaseJobPanicked => write!(f, "a database write panicked"),
+            Error::MissingCredential(name) => write!(f, "missing {name}"),
+            Error::InvalidCredential(name) => write!(f, "invalid {name}"),
+            Error::Config(problems) => write!(f, "invalid configuration: {problems}"),
+            Error::QueueClosed => write!(f, "the queue is closed"),
+            Error::Toml(e) => write!(f, "{e}"),
+            Error::TomlSerialize(e) => write!(f, "{e}"),
+        }
+    }
 }
 
+impl std::error::Error for Error {}
+
 impl From<tdlib::types::Error> for Error {
     fn from(e: tdlib::types::Error) -> Self {
         Self::Tdlib(e)
@@ -52,3 +107,27 @@ impl From<reqwest::Error> for Error {
         Self::Reqwest(e)
     }
 }
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+
+impl From<RowError> for Error {
+    fn from(e: RowError) -> Self {
+        Self::RowDecode(e)
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(e: toml::de::Error) -> Self {
+        Self::Toml(e)
+    }
+}
+
+impl From<toml::ser::Error> for Error {
+    fn from(e: toml::ser::Error) -> Self {
+        Self::TomlSerialize(e)
+    }
+}
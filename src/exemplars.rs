@@ -0,0 +1,149 @@
+use std::collections::HashSet;
+
+use log::error;
+use tdlib::types::Message;
+
+use crate::{
+    database::Database,
+    error::AlterResult,
+    models::{
+        decode_rows, message_provenance::NOT_GENERATED, message_wrapper::MessageWrapper,
+        AutoRequestable,
+    },
+    utils,
+};
+
+const MAX_EXEMPLARS: usize = 3;
+const MAX_CANDIDATES: i64 = 20;
+const MAX_QUERY_WORDS: usize = 16;
+const MIN_WORD_LENGTH: usize = 3;
+// My reply must follow the message quickly enough to be an answer to it
+const MAX_REPLY_DELAY: i32 = 30 * 60;
+const MAX_REPLY_MESSAGES: i64 = 5;
+
+struct Exemplar {
+    question: String,
+    answer: String,
+}
+
+/// Finds the past messages of the chat looking the most like this one which I answered
+/// myself, and renders them with my answers as a section of a system prompt.
+pub async fn prompt_section(db: &Database, message: &Message) -> AlterResult<Option<String>> {
+    let Some(query) = utils::message_text(message).and_then(|text| query(&text)) else {
+        return Ok(None);
+    };
+    let (chat_id, message_id) = (message.chat_id, message.id);
+    let candidates = db
+        .execute(move |conn| {
+            Ok(conn
+                .prepare(
+                    r#"SELECT MESSAGES.id, MESSAGES.date
+                FROM MESSAGES_FTS
+                JOIN MESSAGES_FTS_KEYS ON MESSAGES_FTS_KEYS.id = MESSAGES_FTS.rowid
+                JOIN MESSAGES ON MESSAGES.chat_id = MESSAGES_FTS_KEYS.chat_id
+                    AND MESSAGES.id = MESSAGES_FTS_KEYS.message_id
+                WHERE MESSAGES_FTS MATCH :query
+                    AND MESSAGES.chat_id = :chat_id
+                    AND MESSAGES.id != :message_id
+                    AND MESSAGES.is_outgoing = 0
+                ORDER BY rank
+                LIMIT :limit"#,
+                )?
+                .query_map(
+                    rusqlite::named_params! {
+                        ":query": query,
+                        ":chat_id": chat_id,
+                        ":message_id": message_id,
+                        ":limit": MAX_CANDIDATES,
+                    },
+                    |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i32>(1)?)),
+                )?
+                .collect::<Result<Vec<_>, _>>()?)
+        })
+        .await?;
+
+    let mut exemplars = Vec::new();
+    for (question_id, question_date) in candidates {
+        if exemplars.len() == MAX_EXEMPLARS {
+            break;
+        }
+        let (Some(question), Some(answer)) = (
+            load_text(db, chat_id, question_id).await?,
+            answer(db, chat_id, question_id, question_date).await?,
+        ) else {
+            continue;
+        };
+        exemplars.push(Exemplar { question, answer });
+    }
+    if exemplars.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(format!(
+        "Examples of how I answered similar messages in this chat:\n\n{}",
+        exemplars
+            .iter()
+            .map(|exemplar| format!("Them: {}\nMe: {}", exemplar.question, exemplar.answer))
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    )))
+}
+
+// Matches any of the significant words of the message
+fn query(text: &str) -> Option<String> {
+    let mut seen = HashSet::new();
+    let words = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= MIN_WORD_LENGTH)
+        .map(str::to_lowercase)
+        .filter(|word| seen.insert(word.clone()))
+        .take(MAX_QUERY_WORDS)
+        .map(|word| format!("\"{word}\""))
+        .collect::<Vec<_>>();
+    (!words.is_empty()).then(|| words.join(" OR "))
+}
+
+async fn load_text(db: &Database, chat_id: i64, message_id: i64) -> AlterResult<Option<String>> {
+    Ok(db
+        .load::<MessageWrapper>((chat_id, message_id))
+        .await?
+        .and_then(|message| utils::message_text(&message)))
+}
+
+// My messages sent right after the question, before anybody else speaks
+async fn answer(
+    db: &Database,
+    chat_id: i64,
+    question_id: i64,
+    question_date: i32,
+) -> AlterResult<Option<String>> {
+    let following = db
+        .execute(move |conn| {
+            decode_rows::<MessageWrapper>(
+                MessageWrapper::TABLE,
+                conn.prepare(&format!(
+                    r#"SELECT rowid AS row_id, * FROM MESSAGES
+                WHERE chat_id = :chat_id AND id > :question_id AND date <= :until
+                    AND {NOT_GENERATED}
+                ORDER BY date, id
+                LIMIT :limit"#
+                ))?
+                .query(rusqlite::named_params! {
+                    ":chat_id": chat_id,
+                    ":question_id": question_id,
+                    ":until": question_date + MAX_REPLY_DELAY,
+                    ":limit": MAX_REPLY_MESSAGES,
+                })?,
+            )
+        })
+        .await?;
+
+    let answer = following
+        .into_iter()
+        .filter_map(|message| message.map_err(|e| error!("{e:#?}")).ok())
+        .skip_while(|message| !message.is_outgoing)
+        .take_while(|message| message.is_outgoing)
+        .filter_map(|message| utils::message_text(&message))
+        .collect::<Vec<_>>();
+    Ok((!answer.is_empty()).then(|| answer.join("\n")))
+}
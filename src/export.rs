@@ -0,0 +1,394 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+use clap::ValueEnum;
+use log::{error, info};
+use regex::Regex;
+use serde_json::json;
+use tdlib::types::Message;
+
+use crate::{
+    args::ExportArgs,
+    database::Database,
+    error::AlterResult,
+    models::{
+        decode_rows, message_provenance::NOT_GENERATED, message_wrapper::MessageWrapper,
+        AutoRequestable,
+    },
+    ollama::{OllamaMessage, OllamaRole},
+    utils,
+};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    /// `{"text": "<|im_start|>user\n...<|im_end|>\n..."}`
+    Chatml,
+    /// `[{"role": "user", "content": "..."}, ...]`, as sent to the Ollama chat API
+    Ollama,
+    /// `{"messages": [{"role": "user", "content": "..."}, ...]}`
+    Openai,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Scrub {
+    Emails,
+    Urls,
+    Phones,
+    Mentions,
+}
+
+struct Scrubber(Vec<(Regex, &'static str)>);
+
+impl Scrubber {
+    fn new(scrub: &[Scrub]) -> Self {
+        // Emails go before mentions, which would otherwise eat their domain
+        let patterns = [
+            (Scrub::Emails, r"[\w.+-]+@[\w-]+(\.[\w-]+)+", "<email>"),
+            (Scrub::Urls, r"(https?://|www\.)\S+", "<url>"),
+            // International numbers, or the usual groupings of national ones, so that dates,
+            // amounts and order numbers are kept
+            (
+                Scrub::Phones,
+                r"\+\d{1,3}(?:[ .-]?\(?\d{1,4}\)?){2,5}|\(\d{3}\) ?\d{3}[ .-]\d{4}|\b\d{3}[.-]\d{3}[.-]\d{4}\b|\b0\d(?:[ .]\d{2}){4}\b",
+                "<phone>",
+            ),
+            (Scrub::Mentions, r"@\w{3,}", "@user"),
+        ];
+        Self(
+            patterns
+                .into_iter()
+                .filter(|(kind, _, _)| scrub.contains(kind))
+                .map(|(_, pattern, replacement)| (Regex::new(pattern).unwrap(), replacement))
+                .collect(),
+        )
+    }
+
+    fn scrub(&self, text: String) -> String {
+        self.0.iter().fold(text, |text, (regex, replacement)| {
+            regex.replace_all(&text, *replacement).into_owned()
+        })
+    }
+}
+
+pub async fn run(db: &Database, args: &ExportArgs) -> AlterResult<()> {
+    let mut output: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    let scrubber = Scrubber::new(&args.scrub);
+    let chat_ids = if args.chat.is_empty() {
+        private_chats(db).await?
+    } else {
+        args.chat.clone()
+    };
+
+    let mut exported = 0;
+    for chat_id in chat_ids {
+        let (since, until) = (args.since, args.until);
+        let messages = db
+            .execute(move |conn| chat_messages(conn, chat_id, since, until))
+            .await?;
+        for turns in samples(&messages, args, &scrubber) {
+            writeln!(output, "{}", format_sample(args, turns))?;
+            exported += 1;
+        }
+    }
+    output.flush()?;
+    info!("Exported {exported} samples");
+    Ok(())
+}
+
+async fn private_chats(db: &Database) -> AlterResult<Vec<i64>> {
+    db.execute(|conn| {
+        Ok(conn
+            .prepare(r#"SELECT DISTINCT chat_id FROM MESSAGES WHERE chat_id > 0 ORDER BY chat_id"#)?
+            .query_map(rusqlite::params![], |row| row.get(0))?
+            .collect::<Result<_, _>>()?)
+    })
+    .await
+}
+
+/// The messages of a chat I wrote myself or received, oldest first.
+fn chat_messages(
+    conn: &rusqlite::Connection,
+    chat_id: i64,
+    since: Option<i64>,
+    until: Option<i64>,
+) -> AlterResult<Vec<Message>> {
+    Ok(decode_rows::<MessageWrapper>(
+        MessageWrapper::TABLE,
+        conn.prepare(&format!(
+            r#"SELECT rowid AS row_id, * FROM MESSAGES
+            WHERE chat_id = :chat_id AND date >= :since AND date < :until
+                AND {NOT_GENERATED}
+            ORDER BY date, id"#
+        ))?
+        .query(rusqlite::named_params! {
+            ":chat_id": chat_id,
+            ":since": since.unwrap_or(i64::MIN),
+            ":until": until.unwrap_or(i64::MAX),
+        })?,
+    )?
+    .into_iter()
+    .filter_map(|message| message.map_err(|e| error!("{e:#?}")).ok())
+    .map(<MessageWrapper as Into<Message>>::into)
+    .collect())
+}
+
+/// Splits a chat into conversations at each silence longer than the session gap, merges
+/// consecutive messages of the same side into one turn, then keeps the conversations
+/// going from a question of the user to an answer of mine.
+fn samples(
+    messages: &[Message],
+    args: &ExportArgs,
+    scrubber: &Scrubber,
+) -> Vec<Vec<OllamaMessage>> {
+    let mut samples = Vec::new();
+    let mut turns: Vec<OllamaMessage> = Vec::new();
+    let mut last_date = None;
+    for message in messages {
+        let Some(text) = utils::message_text(message)
+            .map(|text| scrubber.scrub(text))
+            .filter(|text| !text.trim().is_empty())
+        else {
+            continue;
+        };
+        if last_date.is_some_and(|date| (message.date - date) as i64 > args.session_gap * 60) {
+            samples.push(std::mem::take(&mut turns));
+        }
+        last_date = Some(message.date);
+
+        let role = if message.is_outgoing {
+            OllamaRole::Assistant
+        } else {
+            OllamaRole::User
+        };
+        match turns.last_mut() {
+            Some(turn) if turn.role == role => {
+                turn.content.push('\n');
+                turn.content.push_str(&text);
+            }
+            _ => turns.push(OllamaMessage {
+                role,
+                content: text,
+            }),
+        }
+    }
+    samples.push(turns);
+
+    samples
+        .into_iter()
+        .map(|mut turns| {
+            while turns
+                .last()
+                .is_some_and(|turn| turn.role == OllamaRole::User)
+            {
+                turns.pop();
+            }
+            let start = turns
+                .iter()
+                .position(|turn| turn.role == OllamaRole::User)
+                .unwrap_or(turns.len());
+            turns.split_off(start)
+        })
+        .filter(|turns| turns.len() >= args.min_turns.max(2))
+        .collect()
+}
+
+fn format_sample(args: &ExportArgs, turns: Vec<OllamaMessage>) -> String {
+    let messages = args
+        .system
+        .iter()
+        .map(|system| OllamaMessage {
+            role: OllamaRole::System,
+            content: system.clone(),
+        })
+        .chain(turns)
+        .collect::<Vec<_>>();
+    match args.format {
+        ExportFormat::Chatml => json!({
+            "text": messages
+                .iter()
+                .map(|message| format!(
+                    "<|im_start|>{}\n{}<|im_end|>\n",
+                    message.role.as_str(),
+                    message.content
+                ))
+                .collect::<String>()
+        }),
+        ExportFormat::Ollama => json!(messages),
+        ExportFormat::Openai => json!({ "messages": messages }),
+    }
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+
+    use super::*;
+    use crate::migrations;
+
+    const CHAT_ID: i64 = 7;
+    const MY_ID: i64 = 1;
+    const THEIR_ID: i64 = 2;
+
+    fn args(scrub: Vec<Scrub>) -> ExportArgs {
+        ExportArgs {
+            output: None,
+            format: ExportFormat::Ollama,
+            chat: vec![],
+            since: None,
+            until: None,
+            min_turns: 2,
+            session_gap: 30,
+            system: None,
+            scrub,
+        }
+    }
+
+    fn insert_message(conn: &Connection, id: i64, date: i64, is_outgoing: bool, text: &str) {
+        let sender_id = if is_outgoing { MY_ID } else { THEIR_ID };
+        conn.execute(
+            r#"INSERT INTO MESSAGES (
+                id, sender_id, chat_id, is_outgoing, is_pinned, can_be_edited,
+                can_be_forwarded, can_be_saved, can_be_deleted_only_for_self,
+                can_be_deleted_for_all_users, can_get_added_reactions, can_get_statistics,
+                can_get_message_thread, can_get_viewers, can_get_media_timestamp_links,
+                can_report_reactions, has_timestamped_media, is_channel_post, is_topic_message,
+                contains_unread_mention, date, edit_date, unread_reactions, message_thread_id,
+                self_destruct_in, auto_delete_in, via_bot_user_id, author_signature,
+                media_album_id, restriction_reason, content, sending_state, scheduling_state,
+                forward_info, interaction_info, reply_to, self_destruct_type, reply_markup
+            ) VALUES (
+                :id, :sender_id, :chat_id, :is_outgoing, 0, 0,
+                0, 0, 0,
+                0, 0, 0,
+                0, 0, 0,
+                0, 0, 0, 0,
+                0, :date, 0, '[]', 0,
+                0, 0, 0, '',
+                0, '""', :content, 'null', 'null',
+                'null', 'null', 'null', 'null', 'null'
+            )"#,
+            rusqlite::named_params! {
+                ":id": id,
+                ":sender_id": serde_json::json!({
+                    "@type": "messageSenderUser",
+                    "user_id": sender_id,
+                })
+                .to_string(),
+                ":chat_id": CHAT_ID,
+                ":is_outgoing": is_outgoing,
+                ":date": date,
+                ":content": serde_json::json!({
+                    "@type": "messageText",
+                    "text": { "@type": "formattedText", "text": text, "entities": [] },
+                })
+                .to_string(),
+            },
+        )
+        .unwrap();
+    }
+
+    fn turn(role: OllamaRole, content: &str) -> (OllamaRole, String) {
+        (role, content.to_owned())
+    }
+
+    #[test]
+    fn scrubs_phone_numbers_only() {
+        let scrubber = Scrubber::new(&[Scrub::Phones]);
+        for phone in [
+            "+33 6 12 34 56 78",
+            "+1 (555) 123-4567",
+            "+447911123456",
+            "06 12 34 56 78",
+            "(555) 123-4567",
+            "555-123-4567",
+        ] {
+            assert_eq!(
+                scrubber.scrub(format!("Call {phone} now")),
+                "Call <phone> now",
+                "{phone}"
+            );
+        }
+        for kept in [
+            "See you 2024-01-15 10:30",
+            "It costs 1 250 000 €",
+            "Order 123456789 shipped",
+            "Paid 12.50 on 15.01.2024",
+        ] {
+            assert_eq!(scrubber.scrub(kept.to_owned()), kept);
+        }
+    }
+
+    #[test]
+    fn scrubs_emails_before_mentions() {
+        let scrubber = Scrubber::new(&[Scrub::Mentions, Scrub::Emails, Scrub::Urls]);
+        assert_eq!(
+            scrubber.scrub("Ask @someone at jane.doe@example.org or https://example.org/a".into()),
+            "Ask @user at <email> or <url>"
+        );
+        assert_eq!(
+            Scrubber::new(&[]).scrub("jane@example.org".into()),
+            "jane@example.org"
+        );
+    }
+
+    #[test]
+    fn merges_turns_and_leaves_generated_messages_out() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrations::run(&mut conn).unwrap();
+        insert_message(&conn, 1, 1000, false, "Salut");
+        insert_message(
+            &conn,
+            2,
+            1010,
+            false,
+            "Tu m'appelles au +33 6 12 34 56 78 ?",
+        );
+        insert_message(&conn, 3, 1020, true, "Oui");
+        insert_message(&conn, 4, 1030, true, "Je suis un bot");
+        insert_message(&conn, 5, 1040, true, "À plus");
+        // Unanswered, so left out
+        insert_message(&conn, 6, 1050, false, "Merci");
+        // After a silence, a conversation started by me and never answered
+        insert_message(&conn, 7, 6100, true, "Coucou");
+        insert_message(&conn, 8, 6110, false, "Hey");
+        conn.execute(
+            r#"INSERT INTO MESSAGE_PROVENANCE (
+                chat_id, message_id, model, prompt_hash, latency_ms, approval, created_at
+            ) VALUES (?1, 4, 'mistral', '', 0, 'auto', 1030)"#,
+            rusqlite::params![CHAT_ID],
+        )
+        .unwrap();
+
+        let messages = chat_messages(&conn, CHAT_ID, None, None).unwrap();
+        assert_eq!(
+            messages
+                .iter()
+                .map(|message| message.id)
+                .collect::<Vec<_>>(),
+            vec![1, 2, 3, 5, 6, 7, 8]
+        );
+        let args = args(vec![Scrub::Phones]);
+        let samples = samples(&messages, &args, &Scrubber::new(&args.scrub))
+            .into_iter()
+            .map(|turns| {
+                turns
+                    .into_iter()
+                    .map(|turn| (turn.role, turn.content))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            samples,
+            vec![vec![
+                turn(OllamaRole::User, "Salut\nTu m'appelles au <phone> ?"),
+                turn(OllamaRole::Assistant, "Oui\nÀ plus"),
+            ]]
+        );
+    }
+}
@@ -0,0 +1,467 @@
+use std::{fs::File, io::BufReader, path::Path};
+
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tdlib::types::{Chat, Message, User};
+
+use crate::{
+    database::Database,
+    error::AlterResult,
+    models::{
+        chat_wrapper::ChatWrapper, message_wrapper::MessageWrapper, user_wrapper::UserWrapper,
+        AutoRequestable,
+    },
+    search,
+};
+
+// TDLib message ids are the server ids shifted by 20 bits
+const MESSAGE_ID_SHIFT: u32 = 20;
+const SUPERGROUP_CHAT_ID_OFFSET: i64 = -1_000_000_000_000;
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Export {
+    Full {
+        personal_information: Option<PersonalInformation>,
+        chats: ExportedChats,
+        left_chats: Option<ExportedChats>,
+    },
+    Chat(ExportedChat),
+}
+
+#[derive(Deserialize)]
+struct PersonalInformation {
+    user_id: i64,
+    first_name: String,
+    #[serde(default)]
+    last_name: String,
+    username: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ExportedChats {
+    list: Vec<ExportedChat>,
+}
+
+#[derive(Deserialize)]
+struct ExportedChat {
+    name: Option<String>,
+    r#type: String,
+    id: i64,
+    messages: Vec<ExportedMessage>,
+}
+
+#[derive(Deserialize)]
+struct ExportedMessage {
+    id: i64,
+    r#type: String,
+    date_unixtime: String,
+    edited_unixtime: Option<String>,
+    from_id: Option<String>,
+    reply_to_message_id: Option<i64>,
+    #[serde(default)]
+    text: ExportedText,
+    sticker_emoji: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ExportedText {
+    Plain(String),
+    Rich(Vec<ExportedTextPart>),
+}
+
+impl Default for ExportedText {
+    fn default() -> Self {
+        ExportedText::Plain(String::new())
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ExportedTextPart {
+    Plain(String),
+    Entity { text: String },
+}
+
+impl ExportedText {
+    fn to_plain(&self) -> String {
+        match self {
+            ExportedText::Plain(text) => text.clone(),
+            ExportedText::Rich(parts) => parts
+                .iter()
+                .map(|part| match part {
+                    ExportedTextPart::Plain(text) | ExportedTextPart::Entity { text } => {
+                        text.as_str()
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Imports a Telegram Desktop `result.json`, either of a single chat or of the whole
+/// account. Rows already in the database are left untouched. Media are only imported
+/// through their caption, or their emoji for stickers.
+pub async fn run(db: &Database, path: &Path, me: Option<i64>) -> AlterResult<()> {
+    info!("Importing '{}'", path.display());
+    let export: Export = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+    let (me, chats) = match export {
+        Export::Full {
+            personal_information,
+            chats,
+            left_chats,
+        } => {
+            if let Some(personal_information) = &personal_information {
+                import_user(db, user(personal_information)?)?;
+            }
+            let me = me.or(personal_information.map(|me| me.user_id));
+            let chats = chats
+                .list
+                .into_iter()
+                .chain(left_chats.into_iter().flat_map(|chats| chats.list))
+                .collect();
+            (me, chats)
+        }
+        Export::Chat(chat) => (me, vec![chat]),
+    };
+    if me.is_none() {
+        warn!("Your user id is unknown, pass --me to tell your messages apart");
+    }
+
+    for exported_chat in chats {
+        let Some(chat_id) = chat_id(&exported_chat.r#type, exported_chat.id) else {
+            warn!(
+                "Skipping chat {} of unsupported type '{}'",
+                exported_chat.id, exported_chat.r#type
+            );
+            continue;
+        };
+        let title = exported_chat.name.clone().unwrap_or_default();
+        if exported_chat.r#type == "personal_chat" || exported_chat.r#type == "bot_chat" {
+            import_user(db, private_chat_user(chat_id, &title)?)?;
+        }
+        let chat = ChatWrapper::from(chat(chat_id, &exported_chat.r#type, &title)?);
+        let messages = exported_chat
+            .messages
+            .iter()
+            .filter(|message| message.r#type == "message")
+            .filter_map(|exported| match message(chat_id, me, exported) {
+                Ok(message) => message,
+                Err(e) => {
+                    warn!("[{chat_id}] Skipping message {}: {e:?}", exported.id);
+                    None
+                }
+            })
+            .map(MessageWrapper::from)
+            .collect::<Vec<_>>();
+
+        db.write(move |conn| {
+            if ChatWrapper::select_by_id(chat_id, conn)?.is_none() {
+                chat.insert(conn)?;
+            }
+            let mut imported = 0;
+            for message in &messages {
+                if MessageWrapper::select_by_id(message.get_id(), conn)?.is_none() {
+                    message.insert(conn)?;
+                    search::index(conn, message)?;
+                    imported += 1;
+                }
+            }
+            info!(
+                "[{chat_id}] Imported {imported} messages, {} already stored",
+                messages.len() - imported
+            );
+            Ok(())
+        })?;
+    }
+    db.flush().await
+}
+
+fn import_user(db: &Database, user: User) -> AlterResult<()> {
+    let user = UserWrapper::from(user);
+    db.write(move |conn| {
+        if UserWrapper::select_by_id(user.get_id(), conn)?.is_none() {
+            user.insert(conn)?;
+        }
+        Ok(())
+    })
+}
+
+fn chat_id(chat_type: &str, id: i64) -> Option<i64> {
+    match chat_type {
+        "personal_chat" | "bot_chat" | "saved_messages" => Some(id),
+        "private_group" => Some(-id),
+        "private_supergroup" | "public_supergroup" | "private_channel" | "public_channel" => {
+            Some(SUPERGROUP_CHAT_ID_OFFSET - id)
+        }
+        _ => None,
+    }
+}
+
+// `from_id` looks like "user123" or "channel123"
+fn sender(from_id: &str) -> Option<Value> {
+    if let Some(user_id) = from_id.strip_prefix("user") {
+        Some(json!({ "@type": "messageSenderUser", "user_id": user_id.parse::<i64>().ok()? }))
+    } else if let Some(channel_id) = from_id.strip_prefix("channel") {
+        Some(json!({
+            "@type": "messageSenderChat",
+            "chat_id": SUPERGROUP_CHAT_ID_OFFSET - channel_id.parse::<i64>().ok()?,
+        }))
+    } else {
+        let chat_id = from_id.strip_prefix("chat")?.parse::<i64>().ok()?;
+        Some(json!({ "@type": "messageSenderChat", "chat_id": -chat_id }))
+    }
+}
+
+// The TDLib objects are built from their JSON representation, in which TDLib itself omits
+// every field holding a default value.
+
+fn message(
+    chat_id: i64,
+    me: Option<i64>,
+    exported: &ExportedMessage,
+) -> AlterResult<Option<Message>> {
+    let text = match exported.text.to_plain() {
+        text if !text.is_empty() => text,
+        _ => match &exported.sticker_emoji {
+            Some(emoji) => emoji.clone(),
+            None => return Ok(None),
+        },
+    };
+    let Some(sender_id) = exported.from_id.as_deref().and_then(sender) else {
+        return Ok(None);
+    };
+    let is_outgoing = me.is_some_and(|me| exported.from_id == Some(format!("user{me}")));
+    Ok(Some(serde_json::from_value(json!({
+        "id": exported.id << MESSAGE_ID_SHIFT,
+        "sender_id": sender_id,
+        "chat_id": chat_id,
+        "is_outgoing": is_outgoing,
+        "date": exported.date_unixtime.parse::<i32>().unwrap_or_default(),
+        "edit_date": exported
+            .edited_unixtime
+            .as_deref()
+            .and_then(|date| date.parse::<i32>().ok())
+            .unwrap_or_default(),
+        "reply_to": exported.reply_to_message_id.map(|message_id| json!({
+            "@type": "messageReplyToMessage",
+            "chat_id": chat_id,
+            "message_id": message_id << MESSAGE_ID_SHIFT,
+        })),
+        "content": {
+            "@type": "messageText",
+            "text": { "@type": "formattedText", "text": text, "entities": [] },
+        },
+    }))?))
+}
+
+fn chat(chat_id: i64, chat_type: &str, title: &str) -> AlterResult<Chat> {
+    let r#type = match chat_type {
+        "private_group" => json!({ "@type": "chatTypeBasicGroup", "basic_group_id": -chat_id }),
+        "private_supergroup" | "public_supergroup" | "private_channel" | "public_channel" => {
+            json!({
+                "@type": "chatTypeSupergroup",
+                "supergroup_id": SUPERGROUP_CHAT_ID_OFFSET - chat_id,
+                "is_channel": chat_type.ends_with("channel"),
+            })
+        }
+        _ => json!({ "@type": "chatTypePrivate", "user_id": chat_id }),
+    };
+    Ok(serde_json::from_value(json!({
+        "id": chat_id,
+        "type": r#type,
+        "title": title,
+        "permissions": { "@type": "chatPermissions" },
+        "notification_settings": { "@type": "chatNotificationSettings" },
+        "available_reactions": { "@type": "chatAvailableReactionsAll" },
+        "video_chat": { "@type": "videoChat" },
+    }))?)
+}
+
+fn private_chat_user(user_id: i64, name: &str) -> AlterResult<User> {
+    let (first_name, last_name) = name.split_once(' ').unwrap_or((name, ""));
+    user(&PersonalInformation {
+        user_id,
+        first_name: first_name.into(),
+        last_name: last_name.into(),
+        username: None,
+    })
+}
+
+fn user(personal_information: &PersonalInformation) -> AlterResult<User> {
+    Ok(serde_json::from_value(json!({
+        "id": personal_information.user_id,
+        "first_name": personal_information.first_name,
+        "last_name": personal_information.last_name,
+        "usernames": personal_information.username.as_deref().map(|username| {
+            let username = username.trim_start_matches('@');
+            json!({
+            "@type": "usernames",
+            "active_usernames": [username],
+            "disabled_usernames": [],
+            "editable_username": username,
+            })
+        }),
+        "status": { "@type": "userStatusEmpty" },
+        "type": { "@type": "userTypeRegular" },
+    }))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::*;
+
+    const EXPORT: &str = r#"{
+        "personal_information": {
+            "user_id": 1,
+            "first_name": "Me",
+            "username": "@me"
+        },
+        "chats": {
+            "list": [
+                {
+                    "name": "Jane Doe",
+                    "type": "personal_chat",
+                    "id": 2,
+                    "messages": [
+                        {
+                            "id": 10,
+                            "type": "message",
+                            "date_unixtime": "1700000000",
+                            "from_id": "user2",
+                            "text": "Hi"
+                        },
+                        {
+                            "id": 11,
+                            "type": "message",
+                            "date_unixtime": "1700000010",
+                            "edited_unixtime": "1700000020",
+                            "from_id": "user1",
+                            "reply_to_message_id": 10,
+                            "text": ["Hello ", { "type": "bold", "text": "Jane" }]
+                        },
+                        {
+                            "id": 12,
+                            "type": "service",
+                            "date_unixtime": "1700000030",
+                            "actor_id": "user2",
+                            "text": ""
+                        }
+                    ]
+                },
+                {
+                    "name": "News",
+                    "type": "public_channel",
+                    "id": 1500000000,
+                    "messages": [
+                        {
+                            "id": 5,
+                            "type": "message",
+                            "date_unixtime": "1700000040",
+                            "from_id": "channel1500000000",
+                            "text": "Breaking"
+                        }
+                    ]
+                }
+            ]
+        },
+        "left_chats": {
+            "list": [
+                {
+                    "name": "Old friends",
+                    "type": "private_group",
+                    "id": 300,
+                    "messages": [
+                        {
+                            "id": 7,
+                            "type": "message",
+                            "date_unixtime": "1700000050",
+                            "from_id": "user2",
+                            "sticker_emoji": "👋"
+                        }
+                    ]
+                }
+            ]
+        }
+    }"#;
+
+    struct TestFiles {
+        db: PathBuf,
+        export: PathBuf,
+    }
+
+    impl TestFiles {
+        fn new(name: &str) -> Self {
+            let path = |kind| {
+                std::env::temp_dir().join(format!("alterego-{name}-{}.{kind}", std::process::id()))
+            };
+            let files = Self {
+                db: path("sqlite"),
+                export: path("json"),
+            };
+            let _ = fs::remove_file(&files.db);
+            fs::write(&files.export, EXPORT).unwrap();
+            files
+        }
+    }
+
+    impl Drop for TestFiles {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.export);
+            for suffix in ["", "-wal", "-shm"] {
+                let _ = fs::remove_file(format!("{}{suffix}", self.db.display()));
+            }
+        }
+    }
+
+    async fn messages(db: &Database) -> Vec<(i64, i64, bool, String)> {
+        db.execute(|conn| {
+            Ok(conn
+                .prepare(
+                    r#"SELECT chat_id, id, is_outgoing, json_extract(content, '$.text.text')
+                    FROM MESSAGES ORDER BY date"#,
+                )?
+                .query_map(rusqlite::params![], |row| {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+                })?
+                .collect::<Result<_, _>>()?)
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn imports_a_desktop_export_once() {
+        let files = TestFiles::new("import");
+        let db = Database::new(&files.db).unwrap();
+        run(&db, &files.export, None).await.unwrap();
+
+        let imported = vec![
+            (2, 10 << 20, false, "Hi".to_owned()),
+            (2, 11 << 20, true, "Hello Jane".to_owned()),
+            (-1_001_500_000_000, 5 << 20, false, "Breaking".to_owned()),
+            (-300, 7 << 20, false, "👋".to_owned()),
+        ];
+        assert_eq!(messages(&db).await, imported);
+
+        // Rows already stored are left as they are
+        db.write_confirmed(|conn| {
+            conn.execute(
+                r#"UPDATE MESSAGES SET content = json_set(content, '$.text.text', 'Edited')
+                WHERE id = :id"#,
+                rusqlite::named_params! { ":id": 10 << 20 },
+            )?;
+            Ok(())
+        })
+        .await
+        .unwrap();
+        run(&db, &files.export, None).await.unwrap();
+        let mut expected = imported;
+        expected[0].3 = "Edited".to_owned();
+        assert_eq!(messages(&db).await, expected);
+    }
+}
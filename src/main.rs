@@ -1,64 +1,241 @@
-use std::sync::{Arc, Mutex};
+use std::{sync::Arc, time::Duration};
 
-use crate::application::Application;
+use crate::application::{Account, Application};
+use args::{Command, ConfigCommand};
+use auth::{Authenticator, Credentials};
 use clap::Parser;
+use config::{Config, TelegramConfig};
 use database::Database;
-use error::AlterResult;
+use error::{AlterResult, Error};
 
 mod ai;
+mod api;
 mod application;
 mod args;
+mod audit;
+mod auth;
+mod backfill;
+mod commands;
+mod config;
+mod dashboard;
 mod database;
+mod deleted;
+mod doctor;
 mod error;
+mod exemplars;
+mod export;
+mod import;
+mod metrics;
+mod migrations;
 mod models;
 mod ollama;
+mod queue;
+mod render;
 mod save;
+mod search;
+mod style;
 mod update_stream;
 mod utils;
 
+const METRICS_REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
 #[tokio::main]
 async fn main() -> AlterResult<()> {
-    env_logger::init();
     let args = args::Args::parse();
-    let db = Database::new(&args.database_path)?;
-    Application::new(
-        include!("../app.id"),
-        include_str!("../app.hash"),
-        &args.tg_database_directory,
-    )
-    .run(|update_rx, client_id, mut shutdown_rx| {
-        Box::pin(async move {
-            let db = Arc::new(Mutex::new(db));
-            let (message_tx, message_rx) = tokio::sync::mpsc::unbounded_channel();
-            let ai_handle = tokio::spawn(ai::run(
-                db.clone(),
-                args.model_name,
-                message_rx,
-                client_id,
-                shutdown_rx.resubscribe(),
-            ));
-            loop {
-                tokio::select! {
-                    Some((update, client_id)) = update_rx.recv() => {
-                        save::update(db.clone(), &update, client_id);
-                        match update {
-                            tdlib::enums::Update::NewMessage(message) => {
-                                if let Err(e) = message_tx.send(message.message) {
+    let config = Config::load(&args)?;
+    config::init_logger(&config);
+    if let Some(Command::Config(ConfigCommand::Check)) = args.command {
+        return config::check(&config);
+    }
+    let accounts = config.accounts();
+    let selected = match &args.account {
+        Some(name) => accounts
+            .iter()
+            .find(|account| &account.name == name)
+            .ok_or_else(|| Error::Config(format!("unknown account '{name}'")))?,
+        None => &accounts[0],
+    };
+    if args.migrate_only {
+        for account in &accounts {
+            Database::new(&account.database_path)?;
+        }
+        log::info!("Database migrated, exiting");
+        return Ok(());
+    }
+    if args.command.is_some() {
+        let db = Database::new(&selected.database_path)?;
+        match &args.command {
+            Some(Command::Doctor) => return doctor::run(&db).await,
+            Some(Command::Deleted { chat_id }) => return deleted::run(&db, *chat_id).await,
+            Some(Command::Search { query, limit }) => return search::run(&db, query, *limit).await,
+            Some(Command::Export(export_args)) => return export::run(&db, export_args).await,
+            Some(Command::Import { path, me }) => return import::run(&db, path, *me).await,
+            Some(Command::Replay {
+                chat_id,
+                message_id,
+                model,
+            }) => return audit::replay(&db, &config.ollama, *chat_id, *message_id, model).await,
+            Some(Command::Config(_)) | None => {}
+        }
+    }
+    let backfill_chat_ids = if args.backfill_private_chats {
+        Some(Vec::new())
+    } else {
+        (!args.backfill.is_empty()).then(|| args.backfill.clone())
+    };
+    let config_rx = config::watch(args.clone(), config.clone())?;
+
+    let mut sessions = Vec::new();
+    let mut application_accounts = Vec::new();
+    let mut api_credentials = None;
+    // With several accounts, the identity given by --auth-file and the environment would
+    // log them all in as the same person, so only their own auth files give one
+    let single_account = accounts.len() == 1;
+    for account in &accounts {
+        let mut credentials = match &account.auth_file {
+            Some(path) => Credentials::load(Some(path), single_account)?,
+            None if single_account => Credentials::load(args.auth_file.as_deref(), true)?,
+            None => Credentials::load(args.auth_file.as_deref(), false)?.application(),
+        };
+        credentials.api_id = args.api_id.or(credentials.api_id);
+        credentials.api_hash = args.api_hash.clone().or(credentials.api_hash);
+        // A single application serves every account
+        if api_credentials.is_none() {
+            api_credentials = Some(auth::api_credentials(&credentials)?);
+        }
+        let backfill_chat_ids = backfill_chat_ids
+            .clone()
+            .filter(|_| args.account.is_none() || account.name == selected.name);
+        sessions.push((
+            account.name.clone(),
+            Database::new(&account.database_path)?,
+            Arc::new(ai::Control::default()),
+            backfill_chat_ids,
+        ));
+        application_accounts.push(Account {
+            name: account.name.clone(),
+            telegram: TelegramConfig {
+                database_directory: account.database_directory.clone(),
+                ..config.telegram.clone()
+            },
+            authenticator: Authenticator {
+                credentials,
+                code_source: args.auth_code.clone(),
+                qr_login: args.qr_login,
+            },
+        });
+    }
+    let (api_id, api_hash) = api_credentials.ok_or(Error::MissingCredential("API id"))?;
+
+    let messages_capacity = config.queues.messages_capacity;
+    let metrics_handle = tokio::spawn(metrics::report(METRICS_REPORT_INTERVAL));
+    let metrics_server_handle = match config.metrics.listen {
+        Some(address) => Some(tokio::spawn(metrics::serve(
+            tokio::net::TcpListener::bind(address).await?,
+        ))),
+        None => None,
+    };
+    let dashboard_handle = match (&config.dashboard.listen, &config.dashboard.token) {
+        (Some(address), Some(token)) => Some(tokio::spawn(dashboard::serve(
+            tokio::net::TcpListener::bind(address).await?,
+            token.clone(),
+            sessions
+                .iter()
+                .map(|(name, db, control, _)| dashboard::DashboardAccount {
+                    name: name.clone(),
+                    db: db.clone(),
+                    control: control.clone(),
+                })
+                .collect(),
+            config_rx.clone(),
+        ))),
+        _ => None,
+    };
+    Application::new(api_id, &api_hash, application_accounts, &config.queues)
+        .run(|index, update_rx, client_id, mut shutdown_rx| {
+            let (account_name, db, control, backfill_chat_ids) = sessions[index].clone();
+            let config_rx = config_rx.clone();
+            Box::pin(async move {
+                let (ai_tx, ai_rx) = queue::channel(messages_capacity, "messages", &account_name);
+                let ai_handle = tokio::spawn({
+                    let run = ai::run(
+                        db.clone(),
+                        account_name.clone(),
+                        config_rx.clone(),
+                        control.clone(),
+                        ai_rx,
+                        client_id,
+                        shutdown_rx.resubscribe(),
+                    );
+                    async move {
+                        if let Err(e) = run.await {
+                            log::error!("[{account_name}] Stopped answering messages: {e:#?}");
+                        }
+                    }
+                });
+                let backfill_handle = backfill_chat_ids.map(|chat_ids| {
+                    let db = db.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = backfill::run(db, chat_ids, client_id).await {
+                            log::error!("{e:#?}");
+                        }
+                    })
+                });
+                let style_handle = tokio::spawn(style::run(db.clone(), config_rx.clone()));
+                let audit_handle = tokio::spawn(audit::run(db.clone(), config_rx));
+                loop {
+                    tokio::select! {
+                        Some((update, client_id)) = update_rx.recv() => {
+                            save::update(&db, &update, client_id).await;
+                            if let tdlib::enums::Update::MessageSendSucceeded(sent) = &update {
+                                let message = &sent.message;
+                                let (chat_id, old_message_id) = (message.chat_id, sent.old_message_id);
+                                if let Err(e) = control.message_sent(&db, chat_id, old_message_id, message.id) {
                                     log::error!("{e:#?}");
                                 }
                             }
-                            _ => {}
+                            if let tdlib::enums::Update::NewMessage(new_message) = &update {
+                                let message = &new_message.message;
+                                control.publish(ai::Event::Message {
+                                    chat_id: message.chat_id,
+                                    message_id: message.id,
+                                    is_outgoing: message.is_outgoing,
+                                    date: message.date.into(),
+                                    text: utils::message_text(message),
+                                });
+                            }
+                            match update {
+                                tdlib::enums::Update::NewMessage(_)
+                                | tdlib::enums::Update::ChatAction(_) => {
+                                    if let Err(e) = ai_tx.send((update, client_id)).await {
+                                        log::error!("{e:#?}");
+                                    }
+                                }
+                                _ => {}
+                            }
+                        },
+                        _ = shutdown_rx.recv() => {
+                            log::info!("Received shutdown signal");
+                            break;
                         }
-                    },
-                    _ = shutdown_rx.recv() => {
-                        log::info!("Received shutdown signal");
-                        break;
                     }
                 }
-            }
-            ai_handle.abort();
-            Ok(())
+                ai_handle.abort();
+                style_handle.abort();
+                audit_handle.abort();
+                if let Some(backfill_handle) = backfill_handle {
+                    backfill_handle.abort();
+                }
+                db.flush().await
+            })
         })
-    })
-    .await
+        .await?;
+    metrics_handle.abort();
+    if let Some(metrics_server_handle) = metrics_server_handle {
+        metrics_server_handle.abort();
+    }
+    if let Some(dashboard_handle) = dashboard_handle {
+        dashboard_handle.abort();
+    }
+    Ok(())
 }
@@ -0,0 +1,320 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use axum::{http::header, response::IntoResponse, routing::get, Router};
+use log::{debug, error, info};
+use tokio::net::TcpListener;
+
+use crate::update_stream::UpdateStreamMetrics;
+
+pub const UPDATES: &str = "alterego_updates_total";
+pub const MESSAGES_ANSWERED: &str = "alterego_messages_answered_total";
+pub const MESSAGES_SKIPPED: &str = "alterego_messages_skipped_total";
+pub const MESSAGES_INTERRUPTED: &str = "alterego_messages_interrupted_total";
+pub const MESSAGES_FAILED: &str = "alterego_messages_failed_total";
+pub const LLM_LATENCY: &str = "alterego_llm_latency_seconds";
+pub const LLM_TOKENS: &str = "alterego_llm_tokens_total";
+pub const TYPING_DELAY: &str = "alterego_typing_delay_seconds";
+pub const DB_WRITE: &str = "alterego_db_write_seconds";
+pub const TDLIB_ERRORS: &str = "alterego_tdlib_errors_total";
+
+// Name, type and help of the counters and histograms, in the order they are rendered
+const DESCRIPTIONS: [(&str, &str, &str); 10] = [
+    (UPDATES, "counter", "Updates received from TDLib, by type"),
+    (MESSAGES_ANSWERED, "counter", "Messages answered"),
+    (
+        MESSAGES_SKIPPED,
+        "counter",
+        "Messages left unanswered, by reason",
+    ),
+    (
+        MESSAGES_INTERRUPTED,
+        "counter",
+        "Answers abandoned while being written, by reason",
+    ),
+    (
+        MESSAGES_FAILED,
+        "counter",
+        "Messages which could not be answered",
+    ),
+    (
+        LLM_LATENCY,
+        "histogram",
+        "Time taken by the model to generate an answer",
+    ),
+    (
+        LLM_TOKENS,
+        "counter",
+        "Tokens evaluated by the model, by kind (prompt or completion)",
+    ),
+    (
+        TYPING_DELAY,
+        "histogram",
+        "Time spent pretending to read, think and type before answering",
+    ),
+    (DB_WRITE, "histogram", "Time taken by a database write"),
+    (TDLIB_ERRORS, "counter", "Errors returned by TDLib, by code"),
+];
+
+type QueueValue = fn(&QueueMetrics) -> &AtomicU64;
+
+const QUEUE_DESCRIPTIONS: [(&str, &str, &str, QueueValue); 4] = [
+    (
+        "alterego_queue_depth",
+        "gauge",
+        "Updates waiting in a queue",
+        |metrics| &metrics.depth,
+    ),
+    (
+        "alterego_queue_capacity",
+        "gauge",
+        "Updates a queue can hold",
+        |metrics| &metrics.capacity,
+    ),
+    (
+        "alterego_queue_dropped_total",
+        "counter",
+        "Updates dropped from a full queue",
+        |metrics| &metrics.dropped,
+    ),
+    (
+        "alterego_queue_coalesced_total",
+        "counter",
+        "Updates replaced by a newer one about the same object",
+        |metrics| &metrics.coalesced,
+    ),
+];
+const RECEIVE_QUEUE_DEPTH: (&str, &str) = (
+    "alterego_receive_queue_depth",
+    "Updates received from TDLib waiting to be routed to their account",
+);
+const RECEIVE_BLOCKED: (&str, &str) = (
+    "alterego_receive_blocked_seconds_total",
+    "Time TDLib updates waited for room in the receive queue",
+);
+
+// Upper bounds of the histogram buckets, in seconds
+const BUCKETS: [f64; 14] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1., 2.5, 5., 10., 30., 60., 120.,
+];
+
+type Series = (&'static str, Vec<(&'static str, String)>);
+
+static QUEUES: Mutex<Vec<(QueueLabels, Arc<QueueMetrics>)>> = Mutex::new(Vec::new());
+static UPDATE_STREAM: Mutex<Option<Arc<UpdateStreamMetrics>>> = Mutex::new(None);
+static COUNTERS: Mutex<BTreeMap<Series, u64>> = Mutex::new(BTreeMap::new());
+static HISTOGRAMS: Mutex<BTreeMap<Series, Histogram>> = Mutex::new(BTreeMap::new());
+
+#[derive(Debug, Default)]
+struct Histogram {
+    /// Observations in each bucket, not cumulated
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct QueueLabels {
+    pub queue: &'static str,
+    pub account: String,
+}
+
+#[derive(Debug, Default)]
+pub struct QueueMetrics {
+    pub capacity: AtomicU64,
+    pub depth: AtomicU64,
+    pub max_depth: AtomicU64,
+    /// Updates dropped to make room
+    pub dropped: AtomicU64,
+    /// Updates replaced by a newer one about the same object
+    pub coalesced: AtomicU64,
+}
+
+pub fn register_queue(queue: &'static str, account: &str) -> Arc<QueueMetrics> {
+    let metrics = Arc::new(QueueMetrics::default());
+    QUEUES.lock().unwrap().push((
+        QueueLabels {
+            queue,
+            account: account.into(),
+        },
+        metrics.clone(),
+    ));
+    metrics
+}
+
+pub fn queues() -> Vec<(QueueLabels, Arc<QueueMetrics>)> {
+    QUEUES.lock().unwrap().clone()
+}
+
+pub fn register_update_stream(metrics: Arc<UpdateStreamMetrics>) {
+    *UPDATE_STREAM.lock().unwrap() = Some(metrics);
+}
+
+pub fn increment(name: &'static str, labels: &[(&'static str, &str)]) {
+    add(name, labels, 1);
+}
+
+pub fn add(name: &'static str, labels: &[(&'static str, &str)], value: u64) {
+    *COUNTERS
+        .lock()
+        .unwrap()
+        .entry(series(name, labels))
+        .or_default() += value;
+}
+
+/// Counts an error TDLib answered a request with.
+pub fn tdlib_error(e: &tdlib::types::Error) {
+    increment(TDLIB_ERRORS, &[("code", &e.code.to_string())]);
+}
+
+pub fn observe(name: &'static str, labels: &[(&'static str, &str)], duration: Duration) {
+    let seconds = duration.as_secs_f64();
+    let mut histograms = HISTOGRAMS.lock().unwrap();
+    let histogram = histograms.entry(series(name, labels)).or_default();
+    if let Some(bucket) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+        histogram.buckets[bucket] += 1;
+    }
+    histogram.sum += seconds;
+    histogram.count += 1;
+}
+
+fn series(name: &'static str, labels: &[(&'static str, &str)]) -> Series {
+    (
+        name,
+        labels
+            .iter()
+            .map(|(label, value)| (*label, (*value).to_owned()))
+            .collect(),
+    )
+}
+
+/// Serves the metrics in the Prometheus text format on `/metrics`.
+pub async fn serve(listener: TcpListener) {
+    if let Ok(address) = listener.local_addr() {
+        info!("Serving metrics on http://{address}/metrics");
+    }
+    let app = Router::new().route("/metrics", get(scrape));
+    if let Err(e) = axum::serve(listener, app).await {
+        error!("{e:#?}");
+    }
+}
+
+async fn scrape() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        render(),
+    )
+}
+
+pub fn render() -> String {
+    let mut out = String::new();
+    let counters = COUNTERS.lock().unwrap();
+    let histograms = HISTOGRAMS.lock().unwrap();
+    for (name, kind, help) in DESCRIPTIONS {
+        let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} {kind}");
+        for ((_, labels), value) in counters.iter().filter(|((series, _), _)| *series == name) {
+            let _ = writeln!(out, "{name}{} {value}", format_labels(labels, None));
+        }
+        for ((_, labels), histogram) in histograms.iter().filter(|((series, _), _)| *series == name)
+        {
+            let mut cumulated = 0;
+            for (bound, count) in BUCKETS.iter().zip(histogram.buckets) {
+                cumulated += count;
+                let le = bound.to_string();
+                let _ = writeln!(
+                    out,
+                    "{name}_bucket{} {cumulated}",
+                    format_labels(labels, Some(&le))
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{name}_bucket{} {}\n{name}_sum{} {}\n{name}_count{} {}",
+                format_labels(labels, Some("+Inf")),
+                histogram.count,
+                format_labels(labels, None),
+                histogram.sum,
+                format_labels(labels, None),
+                histogram.count,
+            );
+        }
+    }
+    drop((counters, histograms));
+
+    for (name, kind, help, value) in QUEUE_DESCRIPTIONS {
+        let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} {kind}");
+        for (labels, metrics) in queues() {
+            let labels = [
+                ("queue", labels.queue.to_owned()),
+                ("account", labels.account),
+            ];
+            let _ = writeln!(
+                out,
+                "{name}{} {}",
+                format_labels(&labels, None),
+                value(&metrics).load(Ordering::Relaxed)
+            );
+        }
+    }
+    if let Some(update_stream) = UPDATE_STREAM.lock().unwrap().as_ref() {
+        let (name, help) = RECEIVE_QUEUE_DEPTH;
+        let _ = writeln!(
+            out,
+            "# HELP {name} {help}\n# TYPE {name} gauge\n{name} {}",
+            update_stream.depth.load(Ordering::Relaxed)
+        );
+        let (name, help) = RECEIVE_BLOCKED;
+        let _ = writeln!(
+            out,
+            "# HELP {name} {help}\n# TYPE {name} counter\n{name} {}",
+            update_stream.blocked_ms.load(Ordering::Relaxed) as f64 / 1000.
+        );
+    }
+    out
+}
+
+fn format_labels(labels: &[(&'static str, String)], le: Option<&str>) -> String {
+    let labels = labels
+        .iter()
+        .map(|(label, value)| (*label, value.as_str()))
+        .chain(le.map(|le| ("le", le)))
+        .map(|(label, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{label}=\"{value}\"")
+        })
+        .collect::<Vec<_>>();
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", labels.join(","))
+    }
+}
+
+/// Logs the state of the queues at the given interval.
+pub async fn report(interval: Duration) {
+    loop {
+        tokio::time::sleep(interval).await;
+        for (labels, metrics) in queues() {
+            debug!(
+                "Queue {}[{}]: {}/{} updates, up to {}, {} dropped, {} coalesced",
+                labels.queue,
+                labels.account,
+                metrics.depth.load(Ordering::Relaxed),
+                metrics.capacity.load(Ordering::Relaxed),
+                metrics.max_depth.load(Ordering::Relaxed),
+                metrics.dropped.load(Ordering::Relaxed),
+                metrics.coalesced.load(Ordering::Relaxed),
+            );
+        }
+    }
+}
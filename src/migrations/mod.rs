@@ -0,0 +1,442 @@
+use log::{debug, info};
+use rusqlite::{Connection, Transaction};
+
+use crate::error::{AlterResult, Error};
+
+struct Migration {
+    version: i64,
+    description: &'static str,
+    up: fn(&Transaction) -> AlterResult<()>,
+}
+
+// Migrations are applied in order, each one in its own transaction.
+// Never edit a migration once released, add a new one instead.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Initial schema",
+        up: |tx| Ok(tx.execute_batch(include_str!("v001_initial_schema.sql"))?),
+    },
+    Migration {
+        version: 2,
+        description: "Message deletion log",
+        up: |tx| Ok(tx.execute_batch(include_str!("v002_message_deletions.sql"))?),
+    },
+    Migration {
+        version: 3,
+        description: "Key messages by chat",
+        up: |tx| Ok(tx.execute_batch(include_str!("v003_message_chat_scope.sql"))?),
+    },
+    Migration {
+        version: 4,
+        description: "Message full-text search",
+        up: |tx| Ok(tx.execute_batch(include_str!("v004_message_search.sql"))?),
+    },
+    Migration {
+        version: 5,
+        description: "Style profiles",
+        up: |tx| Ok(tx.execute_batch(include_str!("v005_style_profiles.sql"))?),
+    },
+    Migration {
+        version: 6,
+        description: "Message provenance",
+        up: |tx| Ok(tx.execute_batch(include_str!("v006_message_provenance.sql"))?),
+    },
+    Migration {
+        version: 7,
+        description: "Inference audit log",
+        up: |tx| Ok(tx.execute_batch(include_str!("v007_inference_audit.sql"))?),
+    },
+    Migration {
+        version: 8,
+        description: "Chat reply modes and settings",
+        up: |tx| Ok(tx.execute_batch(include_str!("v008_reply_modes_and_settings.sql"))?),
+    },
+    Migration {
+        version: 9,
+        description: "Drop the unknown deleter of messages",
+        up: |tx| Ok(tx.execute_batch(include_str!("v009_drop_deleted_by.sql"))?),
+    },
+    Migration {
+        version: 10,
+        description: "Record every inference of a message",
+        up: |tx| Ok(tx.execute_batch(include_str!("v010_inference_audit_id.sql"))?),
+    },
+];
+
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+pub fn current_version(conn: &Connection) -> AlterResult<i64> {
+    Ok(conn.query_row(
+        r#"SELECT COALESCE(MAX(version), 0) FROM SCHEMA_VERSION"#,
+        rusqlite::params![],
+        |row| row.get(0),
+    )?)
+}
+
+pub fn run(conn: &mut Connection) -> AlterResult<()> {
+    migrate(conn, latest_version())
+}
+
+fn migrate(conn: &mut Connection, target_version: i64) -> AlterResult<()> {
+    conn.execute(
+        r#"CREATE TABLE IF NOT EXISTS SCHEMA_VERSION (
+            version INTEGER PRIMARY KEY,
+            description TEXT NOT NULL,
+            applied_at INTEGER NOT NULL
+        )"#,
+        rusqlite::params![],
+    )?;
+
+    let current_version = current_version(conn)?;
+    if current_version > latest_version() {
+        return Err(Error::SchemaVersion {
+            found: current_version,
+            supported: latest_version(),
+        });
+    }
+    debug!("Database schema at version {current_version}");
+
+    for migration in MIGRATIONS.iter().filter(|migration| {
+        migration.version > current_version && migration.version <= target_version
+    }) {
+        info!(
+            "Applying migration {}: {}",
+            migration.version, migration.description
+        );
+        let tx = conn.transaction()?;
+        (migration.up)(&tx)?;
+        tx.execute(
+            r#"INSERT INTO SCHEMA_VERSION (
+                version,
+                description,
+                applied_at
+            ) VALUES (
+                :version,
+                :description,
+                strftime('%s', 'now')
+            )"#,
+            rusqlite::named_params! {
+                ":version": migration.version,
+                ":description": migration.description,
+            },
+        )?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    // Name, type, not null and position in the primary key of each column
+    type Columns = Vec<(String, String, bool, i64)>;
+
+    /// A database made by `models::init_db` before migrations existed.
+    fn v0() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(include_str!("fixtures/v0.sql")).unwrap();
+        conn
+    }
+
+    fn at_version(version: i64) -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn, version).unwrap();
+        assert_eq!(current_version(&conn).unwrap(), version);
+        conn
+    }
+
+    fn insert_message(conn: &Connection, chat_id: i64, id: i64, text: &str) {
+        insert_content(
+            conn,
+            chat_id,
+            id,
+            serde_json::json!({
+                "@type": "messageText",
+                "text": { "@type": "formattedText", "text": text, "entities": [] },
+            }),
+        );
+    }
+
+    fn insert_content(conn: &Connection, chat_id: i64, id: i64, content: serde_json::Value) {
+        conn.execute(
+            r#"INSERT INTO MESSAGES (
+                id, sender_id, chat_id, is_outgoing, is_pinned, can_be_edited,
+                can_be_forwarded, can_be_saved, can_be_deleted_only_for_self,
+                can_be_deleted_for_all_users, can_get_added_reactions, can_get_statistics,
+                can_get_message_thread, can_get_viewers, can_get_media_timestamp_links,
+                can_report_reactions, has_timestamped_media, is_channel_post, is_topic_message,
+                contains_unread_mention, date, edit_date, unread_reactions, message_thread_id,
+                self_destruct_in, auto_delete_in, via_bot_user_id, author_signature,
+                media_album_id, restriction_reason, content
+            ) VALUES (
+                :id, '{"@type":"messageSenderUser","user_id":42}', :chat_id, 0, 0, 0,
+                0, 0, 0,
+                0, 0, 0,
+                0, 0, 0,
+                0, 0, 0, 0,
+                0, 1700000000, 0, '[]', 0,
+                0, 0, 0, '',
+                0, '', :content
+            )"#,
+            rusqlite::named_params! {
+                ":id": id,
+                ":chat_id": chat_id,
+                ":content": content.to_string(),
+            },
+        )
+        .unwrap();
+    }
+
+    fn insert_chat_model(conn: &Connection, chat_id: i64, model_name: &str) {
+        conn.execute(
+            r#"INSERT INTO CHAT_LLM_MODELS (chat_id, model_name) VALUES (?1, ?2)"#,
+            rusqlite::params![chat_id, model_name],
+        )
+        .unwrap();
+    }
+
+    fn schema(conn: &Connection) -> BTreeMap<String, Columns> {
+        let tables = conn
+            .prepare(r#"SELECT name FROM sqlite_master WHERE type IN ('table', 'index')"#)
+            .unwrap()
+            .query_map(rusqlite::params![], |row| row.get::<_, String>(0))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        tables
+            .into_iter()
+            .map(|table| {
+                let columns = conn
+                    .prepare(&format!(r#"PRAGMA table_info("{table}")"#))
+                    .unwrap()
+                    .query_map(rusqlite::params![], |row| {
+                        Ok((row.get(1)?, row.get(2)?, row.get(3)?, row.get(5)?))
+                    })
+                    .unwrap()
+                    .collect::<Result<Columns, _>>()
+                    .unwrap();
+                (table, columns)
+            })
+            .collect()
+    }
+
+    fn messages(conn: &Connection) -> Vec<(i64, i64)> {
+        conn.prepare(r#"SELECT chat_id, id FROM MESSAGES ORDER BY chat_id, id"#)
+            .unwrap()
+            .query_map(rusqlite::params![], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn chat_model(conn: &Connection, chat_id: i64) -> String {
+        conn.query_row(
+            r#"SELECT model_name FROM CHAT_LLM_MODELS WHERE chat_id = ?1"#,
+            rusqlite::params![chat_id],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    fn indexed(conn: &Connection) -> Vec<(i64, i64, String)> {
+        conn.prepare(
+            r#"SELECT chat_id, message_id, text FROM MESSAGES_FTS
+            JOIN MESSAGES_FTS_KEYS ON MESSAGES_FTS_KEYS.id = MESSAGES_FTS.rowid
+            ORDER BY chat_id, message_id"#,
+        )
+        .unwrap()
+        .query_map(rusqlite::params![], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap()
+    }
+
+    /// Upgrades to the latest version, which must look like a database created from scratch.
+    fn upgrade(conn: &mut Connection) {
+        run(conn).unwrap();
+        assert_eq!(current_version(conn).unwrap(), latest_version());
+        assert_eq!(schema(conn), schema(&at_version(latest_version())));
+        // Upgrading again is a no-op
+        run(conn).unwrap();
+        assert_eq!(current_version(conn).unwrap(), latest_version());
+    }
+
+    #[test]
+    fn upgrades_v0() {
+        let mut conn = v0();
+
+        upgrade(&mut conn);
+        assert_eq!(messages(&conn), vec![(-2, 11), (1, 10)]);
+        assert_eq!(
+            indexed(&conn),
+            vec![
+                (-2, 11, "Hello everyone".to_owned()),
+                (1, 10, "Salut".to_owned())
+            ]
+        );
+        assert_eq!(chat_model(&conn, 1), "mistral");
+        let kept: (String, String, i64) = conn
+            .query_row(
+                r#"SELECT CHATS.title, USERS.first_name, BASIC_GROUPS.member_count
+                FROM CHATS, USERS, BASIC_GROUPS
+                WHERE CHATS.id = 1 AND USERS.id = 1 AND BASIC_GROUPS.id = 2"#,
+                rusqlite::params![],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(kept, ("Jane Doe".to_owned(), "Jane".to_owned(), 3));
+    }
+
+    #[test]
+    fn upgrades_v1() {
+        let mut conn = at_version(1);
+        insert_message(&conn, 1, 10, "Salut");
+        insert_message(&conn, 2, 11, "Ça va ?");
+        insert_chat_model(&conn, 2, "llama");
+
+        upgrade(&mut conn);
+        assert_eq!(messages(&conn), vec![(1, 10), (2, 11)]);
+        assert_eq!(chat_model(&conn, 2), "llama");
+    }
+
+    #[test]
+    fn upgrades_v2() {
+        let mut conn = at_version(2);
+        insert_message(&conn, 1, 10, "Salut");
+        conn.execute(
+            r#"INSERT INTO MESSAGE_DELETIONS (chat_id, message_id, deleted_at, reason)
+            VALUES (1, 9, 1700000000, 'deleted')"#,
+            rusqlite::params![],
+        )
+        .unwrap();
+
+        upgrade(&mut conn);
+        assert_eq!(messages(&conn), vec![(1, 10)]);
+        let deletion: (i64, String) = conn
+            .query_row(
+                r#"SELECT deleted_at, reason FROM MESSAGE_DELETIONS
+                WHERE chat_id = 1 AND message_id = 9"#,
+                rusqlite::params![],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(deletion, (1700000000, "deleted".to_owned()));
+    }
+
+    #[test]
+    fn upgrades_v3() {
+        let mut conn = at_version(3);
+        // Message ids are only unique within a chat since version 3
+        insert_message(&conn, 1, 10, "Salut");
+        insert_message(&conn, 2, 10, " Salut aussi ");
+        insert_message(&conn, 2, 11, "  ");
+        insert_content(
+            &conn,
+            2,
+            12,
+            serde_json::json!({
+                "@type": "messageDocument",
+                "document": { "file_name": "notes.pdf" },
+                "caption": { "text": "Mes notes" },
+            }),
+        );
+        insert_content(
+            &conn,
+            2,
+            13,
+            serde_json::json!({
+                "@type": "messageAudio",
+                "audio": { "performer": "Daft Punk", "title": "" },
+                "caption": { "text": "Écoute ça" },
+            }),
+        );
+        insert_content(
+            &conn,
+            2,
+            14,
+            serde_json::json!({ "@type": "messageLocation" }),
+        );
+
+        upgrade(&mut conn);
+        assert_eq!(
+            messages(&conn),
+            vec![(1, 10), (2, 10), (2, 11), (2, 12), (2, 13), (2, 14)]
+        );
+        assert_eq!(
+            indexed(&conn),
+            vec![
+                (1, 10, "Salut".to_owned()),
+                (2, 10, "Salut aussi".to_owned()),
+                (2, 12, "notes.pdf Mes notes".to_owned()),
+                (2, 13, "Daft Punk Écoute ça".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn upgrades_v7() {
+        let mut conn = at_version(7);
+        conn.execute(
+            r#"INSERT INTO INFERENCE_AUDIT (
+                chat_id, message_id, model, endpoint, request, completion, prompt_tokens,
+                completion_tokens, inference_ms, waiting_ms, outcome, created_at
+            ) VALUES (1, 10, 'mistral', 'chat', '{}', 'Salut', 12, 3, 800, 2000, 'sent', 1700000000)"#,
+            rusqlite::params![],
+        )
+        .unwrap();
+
+        upgrade(&mut conn);
+        // Answering the same message again is recorded next to the first inference
+        conn.execute(
+            r#"INSERT INTO INFERENCE_AUDIT (
+                chat_id, message_id, model, endpoint, request, completion, prompt_tokens,
+                completion_tokens, inference_ms, outcome, created_at
+            ) VALUES (1, 10, 'llama', 'chat', '{}', 'Coucou', 12, 2, 600, 'pending', 1700000100)"#,
+            rusqlite::params![],
+        )
+        .unwrap();
+        let inferences = conn
+            .prepare(r#"SELECT id, model, waiting_ms FROM INFERENCE_AUDIT ORDER BY id"#)
+            .unwrap()
+            .query_map(rusqlite::params![], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap()
+            .collect::<Result<Vec<(i64, String, Option<i64>)>, _>>()
+            .unwrap();
+        assert_eq!(
+            inferences,
+            vec![
+                (1, "mistral".to_owned(), Some(2000)),
+                (2, "llama".to_owned(), None)
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_newer_schema() {
+        let mut conn = at_version(latest_version());
+        conn.execute(
+            r#"INSERT INTO SCHEMA_VERSION (version, description, applied_at)
+            VALUES (?1, 'From the future', 0)"#,
+            rusqlite::params![latest_version() + 1],
+        )
+        .unwrap();
+
+        match run(&mut conn) {
+            Err(Error::SchemaVersion { found, supported }) => {
+                assert_eq!(found, latest_version() + 1);
+                assert_eq!(supported, latest_version());
+            }
+            result => panic!("expected a schema version error, got {result:?}"),
+        }
+    }
+}
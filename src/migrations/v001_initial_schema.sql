@@ -0,0 +1,179 @@
+CREATE TABLE IF NOT EXISTS BASIC_GROUPS (
+    id INTEGER PRIMARY KEY,
+    member_count INTEGER NOT NULL,
+    status TEXT NOT NULL,
+    is_active BOOLEAN NOT NULL,
+    upgraded_to_supergroup_id INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS CHAT_LLM_MODELS (
+    chat_id INTEGER PRIMARY KEY,
+    model_name TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS CHATS (
+    id INTEGER PRIMARY KEY,
+    chat_type TEXT NOT NULL,
+    title TEXT NOT NULL,
+    photo TEXT,
+    permissions TEXT NOT NULL,
+    last_message TEXT,
+    positions TEXT NOT NULL,
+    message_sender_id TEXT,
+    block_list TEXT,
+    has_protected_content BOOLEAN NOT NULL,
+    is_translatable BOOLEAN NOT NULL,
+    is_marked_as_unread BOOLEAN NOT NULL,
+    has_scheduled_messages BOOLEAN NOT NULL,
+    can_be_deleted_only_for_self BOOLEAN NOT NULL,
+    can_be_deleted_for_all_users BOOLEAN NOT NULL,
+    can_be_reported BOOLEAN NOT NULL,
+    default_disable_notification BOOLEAN NOT NULL,
+    unread_count INTEGER NOT NULL,
+    last_read_inbox_message_id INTEGER NOT NULL,
+    last_read_outbox_message_id INTEGER NOT NULL,
+    unread_mention_count INTEGER NOT NULL,
+    unread_reaction_count INTEGER NOT NULL,
+    notification_settings TEXT NOT NULL,
+    available_reactions TEXT NOT NULL,
+    message_auto_delete_time INTEGER NOT NULL,
+    background TEXT,
+    theme_name TEXT NOT NULL,
+    action_bar TEXT,
+    video_chat TEXT NOT NULL,
+    pending_join_requests TEXT,
+    reply_markup_message_id INTEGER NOT NULL,
+    draft_message TEXT,
+    client_data TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS MESSAGES (
+    id INTEGER PRIMARY KEY,
+    sender_id INTEGER NOT NULL,
+    chat_id INTEGER NOT NULL,
+    sending_state TEXT,
+    scheduling_state TEXT,
+    is_outgoing BOOLEAN NOT NULL,
+    is_pinned BOOLEAN NOT NULL,
+    can_be_edited BOOLEAN NOT NULL,
+    can_be_forwarded BOOLEAN NOT NULL,
+    can_be_saved BOOLEAN NOT NULL,
+    can_be_deleted_only_for_self BOOLEAN NOT NULL,
+    can_be_deleted_for_all_users BOOLEAN NOT NULL,
+    can_get_added_reactions BOOLEAN NOT NULL,
+    can_get_statistics BOOLEAN NOT NULL,
+    can_get_message_thread BOOLEAN NOT NULL,
+    can_get_viewers BOOLEAN NOT NULL,
+    can_get_media_timestamp_links BOOLEAN NOT NULL,
+    can_report_reactions BOOLEAN NOT NULL,
+    has_timestamped_media BOOLEAN NOT NULL,
+    is_channel_post BOOLEAN NOT NULL,
+    is_topic_message BOOLEAN NOT NULL,
+    contains_unread_mention BOOLEAN NOT NULL,
+    date INTEGER NOT NULL,
+    edit_date INTEGER NOT NULL,
+    forward_info TEXT,
+    interaction_info TEXT,
+    unread_reactions TEXT NOT NULL,
+    reply_to TEXT,
+    message_thread_id INTEGER NOT NULL,
+    self_destruct_type TEXT,
+    self_destruct_in REAL NOT NULL,
+    auto_delete_in REAL NOT NULL,
+    via_bot_user_id INTEGER NOT NULL,
+    author_signature TEXT NOT NULL,
+    media_album_id INTEGER NOT NULL,
+    restriction_reason TEXT NOT NULL,
+    content TEXT NOT NULL,
+    reply_markup TEXT
+);
+
+CREATE TABLE IF NOT EXISTS MESSAGES_ARCHIVE (
+    id INTEGER PRIMARY KEY,
+    sender_id INTEGER NOT NULL,
+    chat_id INTEGER NOT NULL,
+    sending_state TEXT,
+    scheduling_state TEXT,
+    is_outgoing BOOLEAN NOT NULL,
+    is_pinned BOOLEAN NOT NULL,
+    can_be_edited BOOLEAN NOT NULL,
+    can_be_forwarded BOOLEAN NOT NULL,
+    can_be_saved BOOLEAN NOT NULL,
+    can_be_deleted_only_for_self BOOLEAN NOT NULL,
+    can_be_deleted_for_all_users BOOLEAN NOT NULL,
+    can_get_added_reactions BOOLEAN NOT NULL,
+    can_get_statistics BOOLEAN NOT NULL,
+    can_get_message_thread BOOLEAN NOT NULL,
+    can_get_viewers BOOLEAN NOT NULL,
+    can_get_media_timestamp_links BOOLEAN NOT NULL,
+    can_report_reactions BOOLEAN NOT NULL,
+    has_timestamped_media BOOLEAN NOT NULL,
+    is_channel_post BOOLEAN NOT NULL,
+    is_topic_message BOOLEAN NOT NULL,
+    contains_unread_mention BOOLEAN NOT NULL,
+    date INTEGER NOT NULL,
+    edit_date INTEGER NOT NULL,
+    forward_info TEXT,
+    interaction_info TEXT,
+    unread_reactions TEXT NOT NULL,
+    reply_to TEXT,
+    message_thread_id INTEGER NOT NULL,
+    self_destruct_type TEXT,
+    self_destruct_in REAL NOT NULL,
+    auto_delete_in REAL NOT NULL,
+    via_bot_user_id INTEGER NOT NULL,
+    author_signature TEXT NOT NULL,
+    media_album_id INTEGER NOT NULL,
+    restriction_reason TEXT NOT NULL,
+    content TEXT NOT NULL,
+    reply_markup TEXT
+);
+
+CREATE TABLE IF NOT EXISTS SUPERGROUPS (
+    id INTEGER PRIMARY KEY,
+    usernames TEXT,
+    date INTEGER NOT NULL,
+    status TEXT NOT NULL,
+    member_count INTEGER NOT NULL,
+    has_linked_chat BOOLEAN NOT NULL,
+    has_location BOOLEAN NOT NULL,
+    sign_messages BOOLEAN NOT NULL,
+    join_to_send_messages BOOLEAN NOT NULL,
+    join_by_request BOOLEAN NOT NULL,
+    is_slow_mode_enabled BOOLEAN NOT NULL,
+    is_channel BOOLEAN NOT NULL,
+    is_broadcast_group BOOLEAN NOT NULL,
+    is_forum BOOLEAN NOT NULL,
+    is_verified BOOLEAN NOT NULL,
+    restriction_reason TEXT NOT NULL,
+    is_scam BOOLEAN NOT NULL,
+    is_fake BOOLEAN NOT NULL,
+    has_active_stories BOOLEAN NOT NULL,
+    has_unread_active_stories BOOLEAN NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS USERS (
+    id INTEGER PRIMARY KEY,
+    first_name TEXT NOT NULL,
+    last_name TEXT NOT NULL,
+    usernames TEXT,
+    phone_number TEXT NOT NULL,
+    status TEXT NOT NULL,
+    profile_photo TEXT,
+    emoji_status TEXT,
+    is_contact BOOLEAN NOT NULL,
+    is_mutual_contact BOOLEAN NOT NULL,
+    is_close_friend BOOLEAN NOT NULL,
+    is_verified BOOLEAN NOT NULL,
+    is_premium BOOLEAN NOT NULL,
+    is_support BOOLEAN NOT NULL,
+    restriction_reason TEXT,
+    is_scam BOOLEAN NOT NULL,
+    is_fake BOOLEAN NOT NULL,
+    has_active_stories BOOLEAN NOT NULL,
+    has_unread_active_stories BOOLEAN NOT NULL,
+    have_access BOOLEAN NOT NULL,
+    user_type TEXT NOT NULL,
+    language_code TEXT NOT NULL,
+    added_to_attachment_menu BOOLEAN NOT NULL
+);
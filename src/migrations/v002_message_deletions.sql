@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS MESSAGE_DELETIONS (
+    chat_id INTEGER NOT NULL,
+    message_id INTEGER NOT NULL,
+    deleted_at INTEGER NOT NULL,
+    deleted_by INTEGER,
+    reason TEXT NOT NULL,
+    PRIMARY KEY (chat_id, message_id)
+);
@@ -0,0 +1,92 @@
+-- Telegram message ids are only unique within a chat, so messages are keyed by (chat_id, id).
+-- SQLite cannot alter a primary key, so both tables are rebuilt.
+
+CREATE TABLE MESSAGES_NEW (
+    id INTEGER NOT NULL,
+    sender_id INTEGER NOT NULL,
+    chat_id INTEGER NOT NULL,
+    sending_state TEXT,
+    scheduling_state TEXT,
+    is_outgoing BOOLEAN NOT NULL,
+    is_pinned BOOLEAN NOT NULL,
+    can_be_edited BOOLEAN NOT NULL,
+    can_be_forwarded BOOLEAN NOT NULL,
+    can_be_saved BOOLEAN NOT NULL,
+    can_be_deleted_only_for_self BOOLEAN NOT NULL,
+    can_be_deleted_for_all_users BOOLEAN NOT NULL,
+    can_get_added_reactions BOOLEAN NOT NULL,
+    can_get_statistics BOOLEAN NOT NULL,
+    can_get_message_thread BOOLEAN NOT NULL,
+    can_get_viewers BOOLEAN NOT NULL,
+    can_get_media_timestamp_links BOOLEAN NOT NULL,
+    can_report_reactions BOOLEAN NOT NULL,
+    has_timestamped_media BOOLEAN NOT NULL,
+    is_channel_post BOOLEAN NOT NULL,
+    is_topic_message BOOLEAN NOT NULL,
+    contains_unread_mention BOOLEAN NOT NULL,
+    date INTEGER NOT NULL,
+    edit_date INTEGER NOT NULL,
+    forward_info TEXT,
+    interaction_info TEXT,
+    unread_reactions TEXT NOT NULL,
+    reply_to TEXT,
+    message_thread_id INTEGER NOT NULL,
+    self_destruct_type TEXT,
+    self_destruct_in REAL NOT NULL,
+    auto_delete_in REAL NOT NULL,
+    via_bot_user_id INTEGER NOT NULL,
+    author_signature TEXT NOT NULL,
+    media_album_id INTEGER NOT NULL,
+    restriction_reason TEXT NOT NULL,
+    content TEXT NOT NULL,
+    reply_markup TEXT,
+    PRIMARY KEY (chat_id, id)
+);
+INSERT INTO MESSAGES_NEW SELECT * FROM MESSAGES;
+DROP TABLE MESSAGES;
+ALTER TABLE MESSAGES_NEW RENAME TO MESSAGES;
+
+CREATE TABLE MESSAGES_ARCHIVE_NEW (
+    id INTEGER NOT NULL,
+    sender_id INTEGER NOT NULL,
+    chat_id INTEGER NOT NULL,
+    sending_state TEXT,
+    scheduling_state TEXT,
+    is_outgoing BOOLEAN NOT NULL,
+    is_pinned BOOLEAN NOT NULL,
+    can_be_edited BOOLEAN NOT NULL,
+    can_be_forwarded BOOLEAN NOT NULL,
+    can_be_saved BOOLEAN NOT NULL,
+    can_be_deleted_only_for_self BOOLEAN NOT NULL,
+    can_be_deleted_for_all_users BOOLEAN NOT NULL,
+    can_get_added_reactions BOOLEAN NOT NULL,
+    can_get_statistics BOOLEAN NOT NULL,
+    can_get_message_thread BOOLEAN NOT NULL,
+    can_get_viewers BOOLEAN NOT NULL,
+    can_get_media_timestamp_links BOOLEAN NOT NULL,
+    can_report_reactions BOOLEAN NOT NULL,
+    has_timestamped_media BOOLEAN NOT NULL,
+    is_channel_post BOOLEAN NOT NULL,
+    is_topic_message BOOLEAN NOT NULL,
+    contains_unread_mention BOOLEAN NOT NULL,
+    date INTEGER NOT NULL,
+    edit_date INTEGER NOT NULL,
+    forward_info TEXT,
+    interaction_info TEXT,
+    unread_reactions TEXT NOT NULL,
+    reply_to TEXT,
+    message_thread_id INTEGER NOT NULL,
+    self_destruct_type TEXT,
+    self_destruct_in REAL NOT NULL,
+    auto_delete_in REAL NOT NULL,
+    via_bot_user_id INTEGER NOT NULL,
+    author_signature TEXT NOT NULL,
+    media_album_id INTEGER NOT NULL,
+    restriction_reason TEXT NOT NULL,
+    content TEXT NOT NULL,
+    reply_markup TEXT,
+    PRIMARY KEY (chat_id, id)
+);
+INSERT INTO MESSAGES_ARCHIVE_NEW SELECT * FROM MESSAGES_ARCHIVE;
+DROP TABLE MESSAGES_ARCHIVE;
+ALTER TABLE MESSAGES_ARCHIVE_NEW RENAME TO MESSAGES_ARCHIVE;
@@ -0,0 +1,58 @@
+-- MESSAGES has a composite key, so the FTS rowid is mapped to it through MESSAGES_FTS_KEYS.
+CREATE TABLE IF NOT EXISTS MESSAGES_FTS_KEYS (
+    id INTEGER PRIMARY KEY,
+    chat_id INTEGER NOT NULL,
+    message_id INTEGER NOT NULL,
+    UNIQUE (chat_id, message_id)
+);
+CREATE VIRTUAL TABLE IF NOT EXISTS MESSAGES_FTS USING fts5(
+    text,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+-- Indexes the messages stored so far with the text `render::content_text` gives them:
+-- the trimmed non-empty parts of the content, joined by a space.
+CREATE TEMP TABLE MESSAGES_FTS_BACKFILL AS
+SELECT chat_id, message_id, text FROM (
+    SELECT
+        chat_id,
+        message_id,
+        first || CASE WHEN first != '' AND second != '' THEN ' ' ELSE '' END || second
+            || CASE WHEN (first != '' OR second != '') AND third != '' THEN ' ' ELSE '' END
+            || third AS text
+    FROM (
+        SELECT
+            chat_id,
+            id AS message_id,
+            trim(COALESCE(CASE json_extract(content, '$."@type"')
+                WHEN 'messageText' THEN json_extract(content, '$.text.text')
+                WHEN 'messagePhoto' THEN json_extract(content, '$.caption.text')
+                WHEN 'messageVideo' THEN json_extract(content, '$.caption.text')
+                WHEN 'messageAnimation' THEN json_extract(content, '$.caption.text')
+                WHEN 'messageSticker' THEN json_extract(content, '$.sticker.emoji')
+                WHEN 'messageDocument' THEN json_extract(content, '$.document.file_name')
+                WHEN 'messageVoiceNote' THEN json_extract(content, '$.caption.text')
+                WHEN 'messageAudio' THEN json_extract(content, '$.audio.performer')
+            END, ''), char(32, 9, 10, 13)) AS first,
+            trim(COALESCE(CASE json_extract(content, '$."@type"')
+                WHEN 'messageDocument' THEN json_extract(content, '$.caption.text')
+                WHEN 'messageAudio' THEN json_extract(content, '$.audio.title')
+            END, ''), char(32, 9, 10, 13)) AS second,
+            trim(COALESCE(CASE json_extract(content, '$."@type"')
+                WHEN 'messageAudio' THEN json_extract(content, '$.caption.text')
+            END, ''), char(32, 9, 10, 13)) AS third
+        FROM MESSAGES
+        WHERE json_valid(content)
+    )
+)
+WHERE text != '';
+
+INSERT INTO MESSAGES_FTS_KEYS (chat_id, message_id)
+SELECT chat_id, message_id FROM MESSAGES_FTS_BACKFILL;
+
+INSERT INTO MESSAGES_FTS (rowid, text)
+SELECT MESSAGES_FTS_KEYS.id, MESSAGES_FTS_BACKFILL.text
+FROM MESSAGES_FTS_BACKFILL
+JOIN MESSAGES_FTS_KEYS USING (chat_id, message_id);
+
+DROP TABLE MESSAGES_FTS_BACKFILL;
@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS STYLE_PROFILES (
+    chat_id INTEGER PRIMARY KEY,
+    stats TEXT NOT NULL,
+    updated_at INTEGER NOT NULL
+);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS MESSAGE_PROVENANCE (
+    chat_id INTEGER NOT NULL,
+    message_id INTEGER NOT NULL,
+    model TEXT NOT NULL,
+    prompt_hash TEXT NOT NULL,
+    latency_ms INTEGER NOT NULL,
+    approval TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (chat_id, message_id)
+);
@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS INFERENCE_AUDIT (
+    chat_id INTEGER NOT NULL,
+    message_id INTEGER NOT NULL,
+    model TEXT NOT NULL,
+    endpoint TEXT NOT NULL,
+    request TEXT NOT NULL,
+    completion TEXT NOT NULL,
+    prompt_tokens INTEGER NOT NULL,
+    completion_tokens INTEGER NOT NULL,
+    inference_ms INTEGER NOT NULL,
+    waiting_ms INTEGER,
+    outcome TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (chat_id, message_id)
+);
+
+CREATE INDEX IF NOT EXISTS INFERENCE_AUDIT_CREATED_AT ON INFERENCE_AUDIT (created_at);
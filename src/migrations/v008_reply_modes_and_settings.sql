@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS CHAT_REPLY_MODES (
+    chat_id INTEGER PRIMARY KEY,
+    mode TEXT NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS SETTINGS (
+    name TEXT PRIMARY KEY,
+    value TEXT NOT NULL,
+    updated_at INTEGER NOT NULL
+);
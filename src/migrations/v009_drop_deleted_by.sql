@@ -0,0 +1,2 @@
+-- Telegram never tells who deleted a message and alterego deletes none
+ALTER TABLE MESSAGE_DELETIONS DROP COLUMN deleted_by;
@@ -0,0 +1,32 @@
+-- A message may be answered more than once, e.g. again from the API, and each inference
+-- is recorded
+CREATE TABLE INFERENCE_AUDIT_BY_ID (
+    id INTEGER PRIMARY KEY,
+    chat_id INTEGER NOT NULL,
+    message_id INTEGER NOT NULL,
+    model TEXT NOT NULL,
+    endpoint TEXT NOT NULL,
+    request TEXT NOT NULL,
+    completion TEXT NOT NULL,
+    prompt_tokens INTEGER NOT NULL,
+    completion_tokens INTEGER NOT NULL,
+    inference_ms INTEGER NOT NULL,
+    waiting_ms INTEGER,
+    outcome TEXT NOT NULL,
+    created_at INTEGER NOT NULL
+);
+
+INSERT INTO INFERENCE_AUDIT_BY_ID (
+    chat_id, message_id, model, endpoint, request, completion, prompt_tokens,
+    completion_tokens, inference_ms, waiting_ms, outcome, created_at
+)
+SELECT
+    chat_id, message_id, model, endpoint, request, completion, prompt_tokens,
+    completion_tokens, inference_ms, waiting_ms, outcome, created_at
+FROM INFERENCE_AUDIT ORDER BY created_at;
+
+DROP TABLE INFERENCE_AUDIT;
+ALTER TABLE INFERENCE_AUDIT_BY_ID RENAME TO INFERENCE_AUDIT;
+
+CREATE INDEX IF NOT EXISTS INFERENCE_AUDIT_MESSAGE ON INFERENCE_AUDIT (chat_id, message_id);
+CREATE INDEX IF NOT EXISTS INFERENCE_AUDIT_CREATED_AT ON INFERENCE_AUDIT (created_at);
@@ -0,0 +1,56 @@
+use std::str::FromStr;
+
+use alterego_derive::AutoRequestable;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+
+/// Whether alterego answers in a chat. Chats without a mode are answered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplyMode {
+    Auto,
+    /// Until I resume it
+    Paused,
+}
+
+impl ReplyMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReplyMode::Auto => "auto",
+            ReplyMode::Paused => "paused",
+        }
+    }
+}
+
+impl FromStr for ReplyMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ReplyMode::Auto),
+            "paused" => Ok(ReplyMode::Paused),
+            _ => Err(format!("unknown reply mode '{s}'")),
+        }
+    }
+}
+
+impl ToSql for ReplyMode {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for ReplyMode {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value
+            .as_str()?
+            .parse()
+            .map_err(|_| FromSqlError::InvalidType)
+    }
+}
+
+#[derive(Debug, AutoRequestable)]
+#[auto_requestable(table = "CHAT_REPLY_MODES", id = "chat_id")]
+pub struct ChatReplyMode {
+    pub chat_id: i64,
+    pub mode: ReplyMode,
+    pub updated_at: i64,
+}
@@ -0,0 +1,160 @@
+use alterego_derive::AutoRequestable;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+
+use crate::{error::AlterResult, ollama::Endpoint};
+
+use super::{decode_rows, AutoRequestable};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    /// Still generating, or pretending to read and type
+    Pending,
+    Sent,
+    /// Abandoned for a newer message, or because I took over the chat
+    Interrupted,
+    Failed,
+    /// Dropped before it ended, e.g. on shutdown
+    Cancelled,
+}
+
+impl Outcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::Pending => "pending",
+            Outcome::Sent => "sent",
+            Outcome::Interrupted => "interrupted",
+            Outcome::Failed => "failed",
+            Outcome::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl ToSql for Outcome {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for Outcome {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "pending" => Ok(Outcome::Pending),
+            "sent" => Ok(Outcome::Sent),
+            "interrupted" => Ok(Outcome::Interrupted),
+            "failed" => Ok(Outcome::Failed),
+            "cancelled" => Ok(Outcome::Cancelled),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+/// What the model was asked to answer a message, and what it answered. Recorded before the
+/// answer is generated, then completed with the answer and what became of it. A message
+/// answered again gets another record.
+#[derive(Debug, AutoRequestable)]
+#[auto_requestable(table = "INFERENCE_AUDIT")]
+pub struct InferenceAudit {
+    /// Assigned by the database when missing
+    pub id: Option<i64>,
+    pub chat_id: i64,
+    /// The message answered
+    pub message_id: i64,
+    pub model: String,
+    pub endpoint: Endpoint,
+    /// Body of the request, with the messages and options
+    pub request: String,
+    pub completion: String,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub inference_ms: i64,
+    /// Time spent pretending to read, think and type
+    pub waiting_ms: Option<i64>,
+    pub outcome: Outcome,
+    pub created_at: i64,
+}
+
+impl InferenceAudit {
+    /// The last inference answering a message.
+    pub fn select_latest(
+        chat_id: i64,
+        message_id: i64,
+        conn: &rusqlite::Connection,
+    ) -> AlterResult<Option<Self>> {
+        let audits = decode_rows::<InferenceAudit>(
+            Self::TABLE,
+            conn.prepare(
+                r#"SELECT rowid AS row_id, * FROM INFERENCE_AUDIT
+                WHERE chat_id = :chat_id AND message_id = :message_id
+                ORDER BY id DESC LIMIT 1"#,
+            )?
+            .query(rusqlite::named_params! {
+                ":chat_id": chat_id,
+                ":message_id": message_id,
+            })?,
+        )?;
+        Ok(audits.into_iter().next().transpose()?)
+    }
+
+    /// Adds the answer to the last inference of a message still pending.
+    pub fn answer(
+        conn: &rusqlite::Connection,
+        chat_id: i64,
+        message_id: i64,
+        completion: &str,
+        prompt_tokens: i64,
+        completion_tokens: i64,
+        inference_ms: i64,
+    ) -> AlterResult<()> {
+        conn.execute(
+            r#"UPDATE INFERENCE_AUDIT
+            SET completion = :completion, prompt_tokens = :prompt_tokens,
+                completion_tokens = :completion_tokens, inference_ms = :inference_ms
+            WHERE id = (
+                SELECT MAX(id) FROM INFERENCE_AUDIT
+                WHERE chat_id = :chat_id AND message_id = :message_id AND outcome = 'pending'
+            )"#,
+            rusqlite::named_params! {
+                ":chat_id": chat_id,
+                ":message_id": message_id,
+                ":completion": completion,
+                ":prompt_tokens": prompt_tokens,
+                ":completion_tokens": completion_tokens,
+                ":inference_ms": inference_ms,
+            },
+        )?;
+        Ok(())
+    }
+
+    /// Completes the last inference of a message still pending.
+    pub fn finish(
+        conn: &rusqlite::Connection,
+        chat_id: i64,
+        message_id: i64,
+        outcome: Outcome,
+        waiting_ms: Option<i64>,
+    ) -> AlterResult<()> {
+        conn.execute(
+            r#"UPDATE INFERENCE_AUDIT
+            SET outcome = :outcome, waiting_ms = COALESCE(:waiting_ms, waiting_ms)
+            WHERE id = (
+                SELECT MAX(id) FROM INFERENCE_AUDIT
+                WHERE chat_id = :chat_id AND message_id = :message_id AND outcome = 'pending'
+            )"#,
+            rusqlite::named_params! {
+                ":chat_id": chat_id,
+                ":message_id": message_id,
+                ":outcome": outcome,
+                ":waiting_ms": waiting_ms,
+            },
+        )?;
+        Ok(())
+    }
+
+    /// Deletes the records created before the given time, returning how many there were.
+    pub fn prune(conn: &rusqlite::Connection, before: i64) -> AlterResult<usize> {
+        Ok(conn.execute(
+            r#"DELETE FROM INFERENCE_AUDIT WHERE created_at < :before"#,
+            rusqlite::named_params! { ":before": before },
+        )?)
+    }
+}
@@ -0,0 +1,97 @@
+use alterego_derive::AutoRequestable;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+
+use crate::error::AlterResult;
+
+use super::{decode_rows, message_wrapper::MessageWrapper, AutoRequestable};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeletionReason {
+    /// Permanently deleted by a chat member
+    Deleted,
+    /// Removed by its self-destruct or auto-delete timer
+    Expired,
+    /// No longer accessible, e.g. after leaving the chat
+    Inaccessible,
+}
+
+impl DeletionReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeletionReason::Deleted => "deleted",
+            DeletionReason::Expired => "expired",
+            DeletionReason::Inaccessible => "inaccessible",
+        }
+    }
+}
+
+impl ToSql for DeletionReason {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for DeletionReason {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "deleted" => Ok(DeletionReason::Deleted),
+            "expired" => Ok(DeletionReason::Expired),
+            "inaccessible" => Ok(DeletionReason::Inaccessible),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+#[derive(Debug, AutoRequestable)]
+#[auto_requestable(table = "MESSAGE_DELETIONS", id = "chat_id, message_id")]
+pub struct MessageDeletion {
+    pub chat_id: i64,
+    pub message_id: i64,
+    pub deleted_at: i64,
+    pub reason: DeletionReason,
+}
+
+#[derive(Debug)]
+pub struct DeletedMessage {
+    pub deletion: MessageDeletion,
+    /// Archived content, missing when the message was never stored
+    pub message: Option<MessageWrapper>,
+}
+
+impl MessageDeletion {
+    pub fn select_by_chat(
+        chat_id: i64,
+        conn: &rusqlite::Connection,
+    ) -> AlterResult<Vec<DeletedMessage>> {
+        let deletions = decode_rows::<MessageDeletion>(
+            Self::TABLE,
+            conn.prepare(
+                r#"SELECT rowid AS row_id, * FROM MESSAGE_DELETIONS
+                WHERE chat_id = :chat_id
+                ORDER BY deleted_at, message_id"#,
+            )?
+            .query(rusqlite::named_params! { ":chat_id": chat_id })?,
+        )?;
+
+        let mut deleted_messages = Vec::with_capacity(deletions.len());
+        for deletion in deletions {
+            let deletion = deletion?;
+            let message = decode_rows::<MessageWrapper>(
+                "MESSAGES_ARCHIVE",
+                conn.prepare(
+                    r#"SELECT rowid AS row_id, * FROM MESSAGES_ARCHIVE
+                    WHERE id = :id AND chat_id = :chat_id"#,
+                )?
+                .query(rusqlite::named_params! {
+                    ":id": deletion.message_id,
+                    ":chat_id": deletion.chat_id,
+                })?,
+            )?
+            .into_iter()
+            .next()
+            .transpose()?;
+            deleted_messages.push(DeletedMessage { deletion, message });
+        }
+        Ok(deleted_messages)
+    }
+}
@@ -0,0 +1,56 @@
+use alterego_derive::AutoRequestable;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+
+/// Matches the outgoing messages which alterego did not send, in a query on `MESSAGES`.
+pub const NOT_GENERATED: &str = r#"NOT EXISTS (
+    SELECT 1 FROM MESSAGE_PROVENANCE
+    WHERE MESSAGE_PROVENANCE.chat_id = MESSAGES.chat_id
+        AND MESSAGE_PROVENANCE.message_id = MESSAGES.id
+)"#;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Approval {
+    /// Sent without anybody reviewing it
+    Auto,
+    /// Reviewed by me before being sent
+    Approved,
+}
+
+impl Approval {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Approval::Auto => "auto",
+            Approval::Approved => "approved",
+        }
+    }
+}
+
+impl ToSql for Approval {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for Approval {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "auto" => Ok(Approval::Auto),
+            "approved" => Ok(Approval::Approved),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+/// How a message sent by alterego was generated, recorded under the final id of the message
+/// once it is sent.
+#[derive(Debug, AutoRequestable)]
+#[auto_requestable(table = "MESSAGE_PROVENANCE", id = "chat_id, message_id")]
+pub struct MessageProvenance {
+    pub chat_id: i64,
+    pub message_id: i64,
+    pub model: String,
+    pub prompt_hash: String,
+    pub latency_ms: i64,
+    pub approval: Approval,
+    pub created_at: i64,
+}
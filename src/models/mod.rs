@@ -1,24 +1,32 @@
-use rusqlite::Connection;
+use serde::de::DeserializeOwned;
 
 use crate::error::AlterResult;
 
-use self::{
-    basic_group_wrapper::BasicGroupWrapper, chat_llm_model::ChatLlmModel,
-    chat_wrapper::ChatWrapper, message_wrapper::MessageWrapper,
-    supergroup_wrapper::SupergroupWrapper, user_wrapper::UserWrapper,
-};
-
 pub mod basic_group_wrapper;
 pub mod chat_llm_model;
+pub mod chat_reply_mode;
 pub mod chat_wrapper;
+pub mod inference_audit;
+pub mod message_deletion;
+pub mod message_provenance;
 pub mod message_wrapper;
+pub mod setting;
+pub mod style_profile;
 pub mod supergroup_wrapper;
 pub mod user_wrapper;
 
+#[derive(Debug)]
+pub struct RowError {
+    pub table: &'static str,
+    pub row_id: i64,
+    pub error: rusqlite::Error,
+}
+
 pub trait AutoRequestable {
     type UniqueIdentifier;
 
-    fn create_table_request() -> String;
+    const TABLE: &'static str;
+
     fn get_id(&self) -> Self::UniqueIdentifier;
     fn from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error>
     where
@@ -27,31 +35,133 @@ pub trait AutoRequestable {
         id: Self::UniqueIdentifier,
         conn: &rusqlite::Connection,
     ) -> AlterResult<Option<Self>>
-    where
-        Self: std::marker::Sized;
-    fn select_all(conn: &rusqlite::Connection) -> AlterResult<Vec<Self>>
     where
         Self: std::marker::Sized;
     fn insert(&self, conn: &rusqlite::Connection) -> AlterResult<()>;
     fn update(&self, conn: &rusqlite::Connection) -> AlterResult<()>;
+
+    fn upsert(&self, conn: &rusqlite::Connection) -> AlterResult<()>
+    where
+        Self: std::marker::Sized,
+    {
+        if Self::select_by_id(self.get_id(), conn)?.is_some() {
+            self.update(conn)
+        } else {
+            self.insert(conn)
+        }
+    }
+
+    fn select_all(conn: &rusqlite::Connection) -> AlterResult<Vec<Self>>
+    where
+        Self: std::marker::Sized,
+    {
+        Self::scan(conn)?
+            .into_iter()
+            .map(|entity| Ok(entity?))
+            .collect()
+    }
+
+    fn scan(conn: &rusqlite::Connection) -> AlterResult<Vec<Result<Self, RowError>>>
+    where
+        Self: std::marker::Sized,
+    {
+        decode_rows(
+            Self::TABLE,
+            conn.prepare(&format!(
+                r#"SELECT rowid AS row_id, * FROM {}"#,
+                Self::TABLE
+            ))?
+            .query(rusqlite::params![])?,
+        )
+    }
+}
+
+/// Decodes rows selected along with their `rowid AS row_id`, keeping each row's outcome
+/// so that one undecodable row does not hide the others.
+pub fn decode_rows<DatabaseEntity: AutoRequestable>(
+    table: &'static str,
+    mut rows: rusqlite::Rows,
+) -> AlterResult<Vec<Result<DatabaseEntity, RowError>>> {
+    let mut entities = Vec::new();
+    while let Some(row) = rows.next()? {
+        let row_id = row.get("row_id")?;
+        entities.push(DatabaseEntity::from_row(row).map_err(|error| RowError {
+            table,
+            row_id,
+            error,
+        }));
+    }
+    Ok(entities)
+}
+
+pub fn json_column<T: DeserializeOwned>(
+    row: &rusqlite::Row,
+    column: &str,
+) -> Result<T, rusqlite::Error> {
+    let index = row.as_ref().column_index(column)?;
+    serde_json::from_str(&row.get::<_, String>(index)?).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, Box::new(e))
+    })
 }
 
-pub fn init_db(conn: &Connection) -> Result<(), rusqlite::Error> {
-    conn.execute(
-        &BasicGroupWrapper::create_table_request(),
-        rusqlite::params![],
-    )?;
-    conn.execute(&ChatLlmModel::create_table_request(), rusqlite::params![])?;
-    conn.execute(&ChatWrapper::create_table_request(), rusqlite::params![])?;
-    conn.execute(&MessageWrapper::create_table_request(), rusqlite::params![])?;
-    conn.execute(
-        &MessageWrapper::create_archive_table_request(),
-        rusqlite::params![],
-    )?;
-    conn.execute(
-        &SupergroupWrapper::create_table_request(),
-        rusqlite::params![],
-    )?;
-    conn.execute(&UserWrapper::create_table_request(), rusqlite::params![])?;
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use alterego_derive::AutoRequestable;
+    use rusqlite::Connection;
+
+    use super::AutoRequestable;
+
+    #[derive(Debug, PartialEq, AutoRequestable)]
+    #[auto_requestable(table = "NAMES")]
+    struct Name {
+        id: String,
+        value: i64,
+    }
+
+    #[derive(Debug, PartialEq, AutoRequestable)]
+    #[auto_requestable(table = "LINKS", id = "source, target")]
+    struct Link {
+        source: i64,
+        target: i64,
+    }
+
+    fn conn() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            r#"CREATE TABLE NAMES (id TEXT PRIMARY KEY, value INTEGER NOT NULL);
+            CREATE TABLE LINKS (source INTEGER, target INTEGER, PRIMARY KEY (source, target));"#,
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn keys_take_the_type_of_their_fields() {
+        let conn = conn();
+        let mut name = Name {
+            id: "me".into(),
+            value: 1,
+        };
+        name.upsert(&conn).unwrap();
+        name.value = 2;
+        name.upsert(&conn).unwrap();
+        assert_eq!(
+            Name::select_by_id(name.get_id(), &conn).unwrap(),
+            Some(name)
+        );
+    }
+
+    #[test]
+    fn upserts_tables_made_of_their_key() {
+        let conn = conn();
+        let link = Link {
+            source: 1,
+            target: 2,
+        };
+        link.upsert(&conn).unwrap();
+        link.upsert(&conn).unwrap();
+        link.update(&conn).unwrap();
+        assert_eq!(Link::select_by_id((1, 2), &conn).unwrap(), Some(link));
+        assert_eq!(Link::select_all(&conn).unwrap().len(), 1);
+    }
 }
@@ -0,0 +1,40 @@
+//! Settings of the account changed at runtime, overriding the configuration.
+
+use rusqlite::OptionalExtension;
+
+use crate::{error::AlterResult, utils};
+
+/// Replaces the persona of the account configuration
+pub const PERSONA: &str = "persona";
+
+pub fn get(conn: &rusqlite::Connection, name: &str) -> AlterResult<Option<String>> {
+    Ok(conn
+        .query_row(
+            r#"SELECT value FROM SETTINGS WHERE name = :name"#,
+            rusqlite::named_params! { ":name": name },
+            |row| row.get(0),
+        )
+        .optional()?)
+}
+
+pub fn set(conn: &rusqlite::Connection, name: &str, value: &str) -> AlterResult<()> {
+    conn.execute(
+        r#"INSERT INTO SETTINGS (name, value, updated_at) VALUES (:name, :value, :updated_at)
+        ON CONFLICT (name) DO UPDATE SET value = :value, updated_at = :updated_at"#,
+        rusqlite::named_params! {
+            ":name": name,
+            ":value": value,
+            ":updated_at": utils::unix_time(),
+        },
+    )?;
+    Ok(())
+}
+
+/// Goes back to the configured value.
+pub fn remove(conn: &rusqlite::Connection, name: &str) -> AlterResult<()> {
+    conn.execute(
+        r#"DELETE FROM SETTINGS WHERE name = :name"#,
+        rusqlite::named_params! { ":name": name },
+    )?;
+    Ok(())
+}
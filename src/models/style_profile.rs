@@ -0,0 +1,42 @@
+use alterego_derive::AutoRequestable;
+use serde::{Deserialize, Serialize};
+
+use crate::error::AlterResult;
+
+/// Profile of every chat at once
+pub const OVERALL_CHAT_ID: i64 = 0;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct StyleStats {
+    pub message_count: usize,
+    pub average_length: f64,
+    pub emojis_per_message: f64,
+    /// Ratios of messages
+    pub capitalised: f64,
+    pub ending_with_period: f64,
+    pub with_exclamation: f64,
+    pub with_question: f64,
+    pub with_ellipsis: f64,
+    pub common_phrases: Vec<String>,
+    /// Share of messages per detected language, most used first
+    pub languages: Vec<(String, f64)>,
+}
+
+#[derive(Debug, AutoRequestable)]
+#[auto_requestable(table = "STYLE_PROFILES", id = "chat_id")]
+pub struct StyleProfile {
+    pub chat_id: i64,
+    #[column(json)]
+    pub stats: StyleStats,
+    pub updated_at: i64,
+}
+
+impl StyleProfile {
+    /// Deletes the profiles computed before the given time, returning how many there were.
+    pub fn prune(conn: &rusqlite::Connection, before: i64) -> AlterResult<usize> {
+        Ok(conn.execute(
+            r#"DELETE FROM STYLE_PROFILES WHERE updated_at < :before"#,
+            rusqlite::named_params! { ":before": before },
+        )?)
+    }
+}
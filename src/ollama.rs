@@ -1,32 +1,49 @@
-use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use futures::StreamExt;
-use log::info;
+use log::{error, info};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+use serde_json::{json, Value};
 use tdlib::{
     enums::MessageSender,
     types::{Message, MessageSenderUser},
 };
 
 use crate::{
+    config::{AccountConfig, Config, OllamaConfig},
     database::Database,
-    error::AlterResult,
-    models::{chat_llm_model::ChatLlmModel, message_wrapper::MessageWrapper, AutoRequestable},
-    utils,
+    error::{AlterResult, Error},
+    metrics,
+    models::{
+        chat_llm_model::ChatLlmModel, decode_rows, message_wrapper::MessageWrapper, setting,
+        AutoRequestable,
+    },
+    style, utils,
 };
 
+// A chunk of a streamed answer, from either `/api/generate` or `/api/chat`
 #[derive(Debug, Deserialize)]
-struct OllamaResponse {
+struct OllamaChunk {
+    #[serde(default)]
     response: String,
+    message: Option<OllamaMessage>,
+    #[serde(flatten)]
+    usage: OllamaUsage,
+    /// Set instead of the answer when the model fails, e.g. when it is unknown
+    error: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
-struct OllamaChatResponse {
-    message: OllamaMessage,
+/// Only given in the last chunk of a stream
+#[derive(Debug, Default, Deserialize)]
+struct OllamaUsage {
+    #[serde(default)]
+    prompt_eval_count: u64,
+    #[serde(default)]
+    eval_count: u64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum OllamaRole {
     #[serde(rename = "system")]
     System,
@@ -36,111 +53,279 @@ pub enum OllamaRole {
     Assistant,
 }
 
+impl OllamaRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OllamaRole::System => "system",
+            OllamaRole::User => "user",
+            OllamaRole::Assistant => "assistant",
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct OllamaMessage {
     pub role: OllamaRole,
     pub content: String,
 }
 
-pub async fn request(model_name: &str, text: &str) -> AlterResult<String> {
-    let mut stream = reqwest::Client::new()
-        .post("http://localhost:11434/api/generate")
-        .body(
-            json!({
-                "model": model_name,
-                "prompt": text,
-                "stream": true
-            })
-            .to_string(),
-        )
-        .send()
-        .await?
-        .bytes_stream();
-    let mut res = String::new();
-    while let Some(chunk) = stream.next().await {
-        match chunk {
-            Ok(chunk) => {
-                let response: OllamaResponse = serde_json::from_slice(&chunk).unwrap();
-                res.push_str(&response.response);
-            }
-            Err(e) => return Err(e.into()),
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Endpoint {
+    /// A single prompt, for group chats
+    Generate,
+    /// A conversation
+    Chat,
+}
+
+impl Endpoint {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Endpoint::Generate => "generate",
+            Endpoint::Chat => "chat",
         }
     }
-    Ok(res)
+}
+
+impl ToSql for Endpoint {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for Endpoint {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "generate" => Ok(Endpoint::Generate),
+            "chat" => Ok(Endpoint::Chat),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+/// A request ready to be sent to the model.
+pub struct Prompt {
+    pub model_name: String,
+    pub endpoint: Endpoint,
+    /// Body of the request, exactly as sent
+    pub request: String,
+    /// Characters of the prompt, system prompt and conversation included
+    pub prompt_size: usize,
+}
+
+/// How an answer was generated
+pub struct Generation {
+    pub model_name: String,
+    pub prompt_hash: String,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub latency: Duration,
+}
+
+impl OllamaUsage {
+    fn add(&mut self, other: OllamaUsage) {
+        self.prompt_eval_count += other.prompt_eval_count;
+        self.eval_count += other.eval_count;
+    }
+
+    fn record(&self, model_name: &str, latency: Duration) {
+        metrics::observe(metrics::LLM_LATENCY, &[("model", model_name)], latency);
+        for (kind, tokens) in [
+            ("prompt", self.prompt_eval_count),
+            ("completion", self.eval_count),
+        ] {
+            metrics::add(
+                metrics::LLM_TOKENS,
+                &[("model", model_name), ("kind", kind)],
+                tokens,
+            );
+        }
+    }
+}
+
+pub fn request(model_name: &str, text: &str) -> Prompt {
+    Prompt {
+        model_name: model_name.into(),
+        endpoint: Endpoint::Generate,
+        request: json!({
+            "model": model_name,
+            "prompt": text,
+            "stream": true
+        })
+        .to_string(),
+        prompt_size: text.chars().count(),
+    }
 }
 
 pub async fn chat(
-    db: Arc<Mutex<Database>>,
+    db: &Database,
+    config: &Config,
+    account: &AccountConfig,
     assistant_id: i64,
     user_id: i64,
     system: Option<String>,
-    default_model_name: &str,
-) -> AlterResult<OllamaMessage> {
+) -> AlterResult<Prompt> {
     let (model_name, mut messages) =
-        get_conversation(db.clone(), default_model_name, assistant_id, user_id)?;
+        get_conversation(db, config, account, assistant_id, user_id).await?;
     info!("Infering answer to chat using model '{model_name}'");
-    if let Some(system) = system {
+    let persona = match db
+        .execute(|conn| setting::get(conn, setting::PERSONA))
+        .await?
+    {
+        Some(persona) => Some(persona).filter(|persona| !persona.is_empty()),
+        None => account.persona.clone(),
+    };
+    let system = [persona, system, style::prompt_section(db, user_id).await?]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+    if !system.is_empty() {
         let system_message = OllamaMessage {
             role: OllamaRole::System,
-            content: system,
+            content: system.join("\n\n"),
         };
-        messages.push(system_message);
+        messages.insert(0, system_message);
     }
 
+    let body = json!({
+        "model": model_name,
+        "messages": messages,
+        "stream": true
+    })
+    .to_string();
+    let prompt_size = messages
+        .iter()
+        .map(|message| message.content.chars().count())
+        .sum();
+    Ok(Prompt {
+        model_name,
+        endpoint: Endpoint::Chat,
+        request: body,
+        prompt_size,
+    })
+}
+
+/// Sends a recorded request again, to the same endpoint but with another model.
+pub async fn replay(
+    config: &OllamaConfig,
+    endpoint: Endpoint,
+    request: &str,
+    model_name: &str,
+) -> AlterResult<(String, Generation)> {
+    let mut body: Value = serde_json::from_str(request)?;
+    body["model"] = model_name.into();
+    let prompt_size = match (&body["prompt"], &body["messages"]) {
+        (Value::String(prompt), _) => prompt.chars().count(),
+        (_, Value::Array(messages)) => messages
+            .iter()
+            .filter_map(|message| message["content"].as_str())
+            .map(|content| content.chars().count())
+            .sum(),
+        _ => 0,
+    };
+    let prompt = Prompt {
+        model_name: model_name.into(),
+        endpoint,
+        request: body.to_string(),
+        prompt_size,
+    };
+    complete(config, prompt).await
+}
+
+pub async fn complete(config: &OllamaConfig, prompt: Prompt) -> AlterResult<(String, Generation)> {
+    let Prompt {
+        model_name,
+        endpoint,
+        request: body,
+        ..
+    } = prompt;
+    let prompt_hash = utils::hash(&body);
+    let start = Instant::now();
     let mut stream = reqwest::Client::new()
-        .post("http://localhost:11434/api/chat")
-        .body(
-            json!({
-                "model": model_name,
-                "messages": messages,
-                "stream": true
-            })
-            .to_string(),
-        )
+        .post(format!("{}/api/{}", config.url, endpoint.as_str()))
+        .body(body)
         .send()
         .await?
+        .error_for_status()?
         .bytes_stream();
 
     let mut res = String::new();
-    while let Some(chunk) = stream.next().await {
-        match chunk {
-            Ok(chunk) => {
-                let response: OllamaChatResponse = serde_json::from_slice(&chunk).unwrap();
-                res.push_str(&response.message.content);
-            }
-            Err(e) => return Err(e.into()),
+    let mut usage = OllamaUsage::default();
+    // One JSON object per line, which the network may split or join in any way
+    let mut buffer = Vec::new();
+    while let Some(bytes) = stream.next().await {
+        buffer.extend_from_slice(&bytes?);
+        while let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+            let line = buffer.drain(..=end).collect::<Vec<_>>();
+            read_line(&line, &mut res, &mut usage)?;
         }
     }
+    read_line(&buffer, &mut res, &mut usage)?;
+    if res.trim().is_empty() {
+        return Err(Error::Ollama(format!(
+            "'{model_name}' gave an empty answer"
+        )));
+    }
+    let latency = start.elapsed();
+    usage.record(&model_name, latency);
 
-    Ok(OllamaMessage {
-        role: OllamaRole::Assistant,
-        content: res,
-    })
+    Ok((
+        res,
+        Generation {
+            model_name,
+            prompt_hash,
+            prompt_tokens: usage.prompt_eval_count,
+            completion_tokens: usage.eval_count,
+            latency,
+        },
+    ))
+}
+
+fn read_line(line: &[u8], res: &mut String, usage: &mut OllamaUsage) -> AlterResult<()> {
+    if line.trim_ascii().is_empty() {
+        return Ok(());
+    }
+    let chunk: OllamaChunk = serde_json::from_slice(line)?;
+    if let Some(error) = chunk.error {
+        return Err(Error::Ollama(error));
+    }
+    res.push_str(&chunk.response);
+    if let Some(message) = chunk.message {
+        res.push_str(&message.content);
+    }
+    usage.add(chunk.usage);
+    Ok(())
 }
 
-fn get_conversation(
-    db: Arc<Mutex<Database>>,
-    default_model_name: &str,
+async fn get_conversation(
+    db: &Database,
+    config: &Config,
+    account: &AccountConfig,
     assistant_id: i64,
     chat_id: i64,
 ) -> AlterResult<(String, Vec<OllamaMessage>)> {
-    let db = db.lock().unwrap();
     let model_name = db
-        .load::<ChatLlmModel>(chat_id)?
+        .load::<ChatLlmModel>(chat_id)
+        .await?
         .map(|llm| llm.model_name().to_owned())
-        .unwrap_or_else(|| default_model_name.into());
-    let messages = db
-        .execute(|conn| {
-            Ok(conn
-                .prepare("SELECT * FROM MESSAGES WHERE chat_id = ?1")?
-                .query_map(
-                    rusqlite::params![chat_id],
-                    <MessageWrapper as AutoRequestable>::from_row,
+        .unwrap_or_else(|| account.model(config).into());
+    let limit = config.ai.history_messages as i64;
+    let mut rows = db
+        .execute(move |conn| {
+            decode_rows::<MessageWrapper>(
+                MessageWrapper::TABLE,
+                conn.prepare(
+                    r#"SELECT rowid AS row_id, * FROM MESSAGES WHERE chat_id = ?1
+                    ORDER BY date DESC, id DESC LIMIT ?2"#,
                 )?
-                .filter_map(Result::ok)
-                .collect::<Vec<MessageWrapper>>())
-        })?
+                .query(rusqlite::params![chat_id, limit])?,
+            )
+        })
+        .await?;
+    // Oldest first
+    rows.reverse();
+    let messages = rows
         .into_iter()
+        .filter_map(|message| message.map_err(|e| error!("{e:#?}")).ok())
         .map(<MessageWrapper as Into<Message>>::into)
         .map(|message| OllamaMessage {
             role: match message.sender_id {
@@ -153,7 +338,8 @@ fn get_conversation(
                 }
                 MessageSender::Chat(_) => OllamaRole::System,
             },
-            content: utils::message_text(&message).unwrap_or_else(|| "Salut".into()),
+            content: utils::message_text(&message)
+                .unwrap_or_else(|| config.ai.fallback_message.clone()),
         })
         .collect();
     Ok((model_name, messages))
@@ -0,0 +1,379 @@
+use std::{
+    collections::VecDeque,
+    mem::{self, Discriminant},
+    sync::{atomic::Ordering, Arc, Mutex},
+};
+
+use log::warn;
+use tdlib::enums::Update;
+use tokio::sync::Notify;
+
+use crate::{
+    error::{AlterResult, Error},
+    metrics::{self, QueueMetrics},
+};
+
+// Dropping updates is logged once in that many times
+const DROP_WARNING_INTERVAL: u64 = 100;
+
+struct State {
+    updates: VecDeque<(Update, i32)>,
+    senders: usize,
+    receiver: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    capacity: usize,
+    update_available: Notify,
+    room_available: Notify,
+    metrics: Arc<QueueMetrics>,
+}
+
+/// Creates a bounded queue of updates. When it is full, the oldest update which is neither
+/// about a message, nor a new chat, user or group, nor about the authorization is dropped,
+/// and senders only wait when there is no such update. Repeated updates about the state of
+/// the same user, chat or group only keep the last one.
+pub fn channel(capacity: usize, name: &'static str, account: &str) -> (Sender, Receiver) {
+    let metrics = metrics::register_queue(name, account);
+    metrics.capacity.store(capacity as u64, Ordering::Relaxed);
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            updates: VecDeque::with_capacity(capacity),
+            senders: 1,
+            receiver: true,
+        }),
+        capacity,
+        update_available: Notify::new(),
+        room_available: Notify::new(),
+        metrics,
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+pub struct Sender {
+    shared: Arc<Shared>,
+}
+
+impl Sender {
+    pub async fn send(&self, update: (Update, i32)) -> AlterResult<()> {
+        let mut update = Some(update);
+        loop {
+            let room_available = self.shared.room_available.notified();
+            {
+                let mut state = self.shared.state.lock().unwrap();
+                if !state.receiver {
+                    return Err(Error::QueueClosed);
+                }
+                let pending = update.take().unwrap();
+                match self.shared.push(&mut state, pending) {
+                    None => {
+                        drop(state);
+                        self.shared.update_available.notify_one();
+                        return Ok(());
+                    }
+                    Some(pending) => update = Some(pending),
+                }
+            }
+            room_available.await;
+        }
+    }
+}
+
+impl Clone for Sender {
+    fn clone(&self) -> Self {
+        self.shared.state.lock().unwrap().senders += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Drop for Sender {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.senders -= 1;
+        if state.senders == 0 {
+            drop(state);
+            self.shared.update_available.notify_one();
+        }
+    }
+}
+
+pub struct Receiver {
+    shared: Arc<Shared>,
+}
+
+impl Receiver {
+    /// The next update, or `None` once every sender is gone and the queue is empty.
+    pub async fn recv(&mut self) -> Option<(Update, i32)> {
+        loop {
+            let update_available = self.shared.update_available.notified();
+            {
+                let mut state = self.shared.state.lock().unwrap();
+                if let Some(update) = state.updates.pop_front() {
+                    self.shared.record_depth(&state);
+                    drop(state);
+                    self.shared.room_available.notify_one();
+                    return Some(update);
+                }
+                if state.senders == 0 {
+                    return None;
+                }
+            }
+            update_available.await;
+        }
+    }
+}
+
+impl Drop for Receiver {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().receiver = false;
+        self.shared.room_available.notify_waiters();
+    }
+}
+
+impl Shared {
+    // Gives the update back when there is no room for it
+    fn push(&self, state: &mut State, update: (Update, i32)) -> Option<(Update, i32)> {
+        if let Some(key) = coalescing_key(&update.0) {
+            if let Some(index) = state
+                .updates
+                .iter()
+                .position(|(queued, _)| coalescing_key(queued) == Some(key))
+            {
+                state.updates.remove(index);
+                self.metrics.coalesced.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        if state.updates.len() >= self.capacity {
+            match state
+                .updates
+                .iter()
+                .position(|(queued, _)| is_droppable(queued))
+            {
+                Some(index) => {
+                    state.updates.remove(index);
+                    let dropped = self.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+                    if dropped.is_multiple_of(DROP_WARNING_INTERVAL) {
+                        warn!("Update queue full, dropped {} updates so far", dropped + 1);
+                    }
+                }
+                None if is_droppable(&update.0) => {
+                    self.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+                    return None;
+                }
+                None => return Some(update),
+            }
+        }
+        state.updates.push_back(update);
+        self.record_depth(state);
+        None
+    }
+
+    fn record_depth(&self, state: &State) {
+        let depth = state.updates.len() as u64;
+        self.metrics.depth.store(depth, Ordering::Relaxed);
+        self.metrics.max_depth.fetch_max(depth, Ordering::Relaxed);
+    }
+}
+
+fn is_droppable(update: &Update) -> bool {
+    !matches!(
+        update,
+        Update::AuthorizationState(_)
+            | Update::NewMessage(_)
+            | Update::MessageSendSucceeded(_)
+            | Update::MessageSendFailed(_)
+            | Update::MessageContent(_)
+            | Update::MessageEdited(_)
+            | Update::DeleteMessages(_)
+            // Stored, and only sent again once changed
+            | Update::NewChat(_)
+            | Update::User(_)
+            | Update::Supergroup(_)
+            | Update::BasicGroup(_)
+    )
+}
+
+// Updates carrying the whole new state of an object, making the previous ones useless
+fn coalescing_key(update: &Update) -> Option<(Discriminant<Update>, i64)> {
+    let id = match update {
+        Update::User(update) => update.user.id,
+        Update::UserStatus(update) => update.user_id,
+        Update::Supergroup(update) => update.supergroup.id,
+        Update::BasicGroup(update) => update.basic_group.id,
+        Update::ChatTitle(update) => update.chat_id,
+        Update::ChatPhoto(update) => update.chat_id,
+        Update::ChatLastMessage(update) => update.chat_id,
+        Update::ChatReadInbox(update) => update.chat_id,
+        Update::ChatReadOutbox(update) => update.chat_id,
+        _ => return None,
+    };
+    Some((mem::discriminant(update), id))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tdlib::{
+        enums::{AuthorizationState, ChatMemberStatus},
+        types::{
+            BasicGroup, UpdateAuthorizationState, UpdateBasicGroup, UpdateChatReadInbox,
+            UpdateChatReadOutbox, UpdateChatTitle, UpdateDeleteMessages,
+        },
+    };
+
+    use super::*;
+
+    const CLIENT_ID: i32 = 1;
+
+    fn read_inbox(chat_id: i64, last_read_inbox_message_id: i64) -> Update {
+        Update::ChatReadInbox(UpdateChatReadInbox {
+            chat_id,
+            last_read_inbox_message_id,
+            unread_count: 0,
+        })
+    }
+
+    fn read_outbox(chat_id: i64) -> Update {
+        Update::ChatReadOutbox(UpdateChatReadOutbox {
+            chat_id,
+            last_read_outbox_message_id: 0,
+        })
+    }
+
+    fn title(chat_id: i64) -> Update {
+        Update::ChatTitle(UpdateChatTitle {
+            chat_id,
+            title: format!("Chat {chat_id}"),
+        })
+    }
+
+    fn deletion(chat_id: i64) -> Update {
+        Update::DeleteMessages(UpdateDeleteMessages {
+            chat_id,
+            message_ids: vec![1],
+            is_permanent: true,
+            from_cache: false,
+        })
+    }
+
+    fn authorization() -> Update {
+        Update::AuthorizationState(UpdateAuthorizationState {
+            authorization_state: AuthorizationState::Ready,
+        })
+    }
+
+    fn basic_group(id: i64, member_count: i32) -> Update {
+        Update::BasicGroup(UpdateBasicGroup {
+            basic_group: BasicGroup {
+                id,
+                member_count,
+                status: ChatMemberStatus::Member,
+                is_active: true,
+                upgraded_to_supergroup_id: 0,
+            },
+        })
+    }
+
+    async fn send_all(update_tx: &Sender, updates: Vec<Update>) {
+        for update in updates {
+            update_tx.send((update, CLIENT_ID)).await.unwrap();
+        }
+    }
+
+    fn queued(update_rx: &Receiver) -> Vec<Update> {
+        let state = update_rx.shared.state.lock().unwrap();
+        state
+            .updates
+            .iter()
+            .map(|(update, _)| update.clone())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn drops_the_oldest_droppable_update() {
+        let (update_tx, update_rx) = channel(3, "test", "drop");
+        send_all(&update_tx, vec![read_inbox(1, 10), deletion(1), title(2)]).await;
+        send_all(&update_tx, vec![deletion(2)]).await;
+        assert_eq!(queued(&update_rx), vec![deletion(1), title(2), deletion(2)]);
+        assert_eq!(update_rx.shared.metrics.dropped.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn never_drops_messages_nor_the_authorization() {
+        let (update_tx, update_rx) = channel(2, "test", "keep");
+        send_all(&update_tx, vec![authorization(), deletion(1)]).await;
+        // Nothing queued can make room, so the droppable update is the one dropped
+        send_all(&update_tx, vec![title(3)]).await;
+        assert_eq!(queued(&update_rx), vec![authorization(), deletion(1)]);
+        assert_eq!(update_rx.shared.metrics.dropped.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn never_drops_groups_but_keeps_their_last_state() {
+        let (update_tx, update_rx) = channel(1, "test", "group");
+        send_all(&update_tx, vec![basic_group(5, 2), title(1)]).await;
+        assert_eq!(queued(&update_rx), vec![basic_group(5, 2)]);
+        send_all(&update_tx, vec![basic_group(5, 3)]).await;
+        assert_eq!(queued(&update_rx), vec![basic_group(5, 3)]);
+    }
+
+    #[tokio::test]
+    async fn blocks_only_when_nothing_can_be_dropped() {
+        let (update_tx, mut update_rx) = channel(1, "test", "block");
+        send_all(&update_tx, vec![deletion(1)]).await;
+        let blocked_tx = update_tx.clone();
+        let mut blocked =
+            tokio::spawn(async move { blocked_tx.send((deletion(2), CLIENT_ID)).await });
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), &mut blocked)
+                .await
+                .is_err()
+        );
+
+        assert_eq!(update_rx.recv().await.unwrap().0, deletion(1));
+        blocked.await.unwrap().unwrap();
+        assert_eq!(update_rx.recv().await.unwrap().0, deletion(2));
+        assert_eq!(update_rx.shared.metrics.dropped.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn keeps_the_last_update_of_each_kind_and_id() {
+        let (update_tx, update_rx) = channel(10, "test", "coalesce");
+        send_all(
+            &update_tx,
+            vec![
+                read_inbox(1, 10),
+                read_inbox(2, 10),
+                read_outbox(1),
+                read_inbox(1, 11),
+            ],
+        )
+        .await;
+        assert_eq!(
+            queued(&update_rx),
+            vec![read_inbox(2, 10), read_outbox(1), read_inbox(1, 11)]
+        );
+        assert_eq!(
+            update_rx.shared.metrics.coalesced.load(Ordering::Relaxed),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn ends_once_every_sender_is_gone() {
+        let (update_tx, mut update_rx) = channel(2, "test", "close");
+        send_all(&update_tx, vec![deletion(1)]).await;
+        drop(update_tx);
+        assert_eq!(update_rx.recv().await.unwrap().0, deletion(1));
+        assert!(update_rx.recv().await.is_none());
+    }
+}
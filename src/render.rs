@@ -0,0 +1,31 @@
+use tdlib::enums::MessageContent;
+
+/// Renders the searchable text of a message, joining captions with the textual parts of
+/// their media. Returns `None` when there is nothing to read.
+pub fn content_text(content: &MessageContent) -> Option<String> {
+    let parts = match content {
+        MessageContent::MessageText(text) => vec![text.text.text.clone()],
+        MessageContent::MessagePhoto(photo) => vec![photo.caption.text.clone()],
+        MessageContent::MessageVideo(video) => vec![video.caption.text.clone()],
+        MessageContent::MessageAnimation(animation) => vec![animation.caption.text.clone()],
+        MessageContent::MessageSticker(sticker) => vec![sticker.sticker.emoji.clone()],
+        MessageContent::MessageDocument(document) => vec![
+            document.document.file_name.clone(),
+            document.caption.text.clone(),
+        ],
+        MessageContent::MessageVoiceNote(voice_note) => vec![voice_note.caption.text.clone()],
+        MessageContent::MessageAudio(audio) => vec![
+            audio.audio.performer.clone(),
+            audio.audio.title.clone(),
+            audio.caption.text.clone(),
+        ],
+        _ => vec![],
+    };
+    let text = parts
+        .into_iter()
+        .map(|part| part.trim().to_owned())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    (!text.is_empty()).then_some(text)
+}
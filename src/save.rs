@@ -1,73 +1,89 @@
-use std::sync::{Arc, Mutex};
-
 use log::{debug, error, trace};
 use tdlib::{
     enums::{MessageContent, Update},
     functions,
-    types::{Message, UpdateDeleteMessages, UpdateMessageSendSucceeded},
+    types::{Message, UpdateDeleteMessages, UpdateMessageContent, UpdateMessageSendSucceeded},
 };
 
 use crate::{
     database::Database,
     error::AlterResult,
+    metrics,
     models::{
-        basic_group_wrapper::BasicGroupWrapper, chat_wrapper::ChatWrapper,
-        message_wrapper::MessageWrapper, supergroup_wrapper::SupergroupWrapper,
+        basic_group_wrapper::BasicGroupWrapper,
+        chat_wrapper::ChatWrapper,
+        message_deletion::{DeletionReason, MessageDeletion},
+        message_wrapper::MessageWrapper,
+        supergroup_wrapper::SupergroupWrapper,
         user_wrapper::UserWrapper,
+        AutoRequestable,
     },
+    search, utils,
 };
 
-pub fn update(db: Arc<Mutex<Database>>, update: &Update, client_id: i32) {
+pub async fn update(db: &Database, update: &Update, client_id: i32) {
+    metrics::increment(metrics::UPDATES, &[("type", update_type(update))]);
     let result = match update {
         Update::NewMessage(message) => {
-            let db = db.lock().unwrap();
             download_message_content(&message.message, client_id);
-            db.save(&MessageWrapper::from(message.message.clone()))
+            save_message(db, message.message.clone())
         }
         // https://github.com/tdlib/td/issues/511
         Update::MessageSendSucceeded(UpdateMessageSendSucceeded {
             message,
             old_message_id,
-        }) => db
-            .lock()
-            .unwrap()
-            .execute(|conn| {
-                MessageWrapper::from(message.clone())
-                    .update_with_old_id(conn, *old_message_id)
-                    .map(|_| Vec::<MessageWrapper>::new())
+        }) => {
+            let message = MessageWrapper::from(message.clone());
+            let old_message_id = *old_message_id;
+            db.write(move |conn| {
+                message.update_with_old_id(conn, old_message_id)?;
+                search::unindex(conn, message.chat_id, old_message_id)?;
+                search::index(conn, &message)
             })
-            .map(|_| ()),
+        }
+        Update::MessageContent(UpdateMessageContent {
+            chat_id,
+            message_id,
+            new_content,
+        }) => {
+            let (chat_id, message_id, new_content) = (*chat_id, *message_id, new_content.clone());
+            db.write(move |conn| {
+                if let Some(message) = MessageWrapper::select_by_id((chat_id, message_id), conn)? {
+                    let mut message = <MessageWrapper as Into<Message>>::into(message);
+                    message.content = new_content.clone();
+                    let message = MessageWrapper::from(message);
+                    message.update(conn)?;
+                    search::index(conn, &message)?;
+                }
+                Ok(())
+            })
+        }
         Update::DeleteMessages(UpdateDeleteMessages {
-            from_cache: false,
+            chat_id,
             message_ids,
-            ..
+            is_permanent,
+            from_cache: false,
         }) => {
             debug!("Archiving {} messages: {message_ids:?}", message_ids.len());
-            let db = db.lock().unwrap();
-            archive_messages(&db, &message_ids);
-            Ok(())
+            archive_messages(db, *chat_id, message_ids.clone(), *is_permanent, client_id).await
         }
         Update::NewChat(tdlib::types::UpdateNewChat { chat }) => {
             if let Some(photo) = &chat.photo {
                 download_file(photo.big.id, client_id);
             }
-            let db = db.lock().unwrap();
-            db.save(&ChatWrapper::from(chat.clone()))
+            db.save(ChatWrapper::from(chat.clone()))
         }
         Update::Supergroup(tdlib::types::UpdateSupergroup { supergroup }) => {
-            let db = db.lock().unwrap();
-            db.save(&SupergroupWrapper::from(supergroup.clone()))
+            db.save(SupergroupWrapper::from(supergroup.clone()))
         }
         Update::BasicGroup(tdlib::types::UpdateBasicGroup { basic_group }) => {
-            let db = db.lock().unwrap();
-            db.save(&BasicGroupWrapper::from(basic_group.clone()))
+            db.save(BasicGroupWrapper::from(basic_group.clone()))
         }
         Update::User(tdlib::types::UpdateUser { user }) => {
             if let Some(photo) = &user.profile_photo {
                 download_file(photo.big.id, client_id);
             }
-            let db = db.lock().unwrap();
-            db.save(&UserWrapper::from(user.clone()))
+            db.save(UserWrapper::from(user.clone()))
         }
         _ => Ok(()),
     };
@@ -77,21 +93,55 @@ pub fn update(db: Arc<Mutex<Database>>, update: &Update, client_id: i32) {
     }
 }
 
-fn archive_messages(db: &Database, message_ids: &[i64]) {
-    for message_id in message_ids {
-        match db.load::<MessageWrapper>(*message_id) {
-            Ok(Some(message)) => {
-                if let Err(e) = db.execute(|conn| {
-                    message.delete(conn)?;
-                    Ok(vec![]) as AlterResult<Vec<MessageWrapper>>
-                }) {
-                    error!("{e:#?}");
+pub fn save_message(db: &Database, message: Message) -> AlterResult<()> {
+    let message = MessageWrapper::from(message);
+    db.write(move |conn| {
+        message.upsert(conn)?;
+        search::index(conn, &message)
+    })
+}
+
+async fn archive_messages(
+    db: &Database,
+    chat_id: i64,
+    message_ids: Vec<i64>,
+    is_permanent: bool,
+    client_id: i32,
+) -> AlterResult<()> {
+    if db.load::<ChatWrapper>(chat_id).await?.is_none() {
+        load_chat(chat_id, client_id);
+    }
+
+    let deleted_at = utils::unix_time();
+    db.write(move |conn| {
+        for &message_id in &message_ids {
+            if MessageDeletion::select_by_id((chat_id, message_id), conn)?.is_some() {
+                continue;
+            }
+            let message = MessageWrapper::select_by_id((chat_id, message_id), conn)?;
+            let reason = match &message {
+                _ if !is_permanent => DeletionReason::Inaccessible,
+                Some(message)
+                    if message.self_destruct_type.is_some() || message.auto_delete_in > 0. =>
+                {
+                    DeletionReason::Expired
                 }
+                _ => DeletionReason::Deleted,
+            };
+            if let Some(message) = &message {
+                message.delete(conn)?;
+            }
+            search::unindex(conn, chat_id, message_id)?;
+            MessageDeletion {
+                chat_id,
+                message_id,
+                deleted_at,
+                reason,
             }
-            Ok(None) => {}
-            Err(e) => error!("{e:#?}"),
+            .insert(conn)?;
         }
-    }
+        Ok(())
+    })
 }
 
 fn download_message_content(message: &Message, client_id: i32) {
@@ -116,12 +166,52 @@ fn download_message_content(message: &Message, client_id: i32) {
     }
 }
 
+// TDLib answers with an `UpdateNewChat` the first time a chat is loaded
+fn load_chat(chat_id: i64, client_id: i32) {
+    tokio::spawn(async move {
+        if let Err(e) = functions::get_chat(chat_id, client_id)
+            .await
+            .inspect_err(metrics::tdlib_error)
+        {
+            error!("{e:#?}");
+        }
+    });
+}
+
 fn download_file(file_id: i32, client_id: i32) {
     tokio::spawn(async move {
-        if let Err(e) = functions::download_file(file_id, 1, 0, 0, true, client_id).await {
+        if let Err(e) = functions::download_file(file_id, 1, 0, 0, true, client_id)
+            .await
+            .inspect_err(metrics::tdlib_error)
+        {
             error!("{e:#?}");
         } else {
             trace!("Downloaded file: {file_id}");
         }
     });
 }
+
+fn update_type(update: &Update) -> &'static str {
+    match update {
+        Update::AuthorizationState(_) => "authorization_state",
+        Update::NewMessage(_) => "new_message",
+        Update::MessageSendSucceeded(_) => "message_send_succeeded",
+        Update::MessageSendFailed(_) => "message_send_failed",
+        Update::MessageContent(_) => "message_content",
+        Update::MessageEdited(_) => "message_edited",
+        Update::DeleteMessages(_) => "delete_messages",
+        Update::NewChat(_) => "new_chat",
+        Update::ChatTitle(_) => "chat_title",
+        Update::ChatPhoto(_) => "chat_photo",
+        Update::ChatLastMessage(_) => "chat_last_message",
+        Update::ChatPosition(_) => "chat_position",
+        Update::ChatReadInbox(_) => "chat_read_inbox",
+        Update::ChatReadOutbox(_) => "chat_read_outbox",
+        Update::ChatAction(_) => "chat_action",
+        Update::User(_) => "user",
+        Update::UserStatus(_) => "user_status",
+        Update::Supergroup(_) => "supergroup",
+        Update::BasicGroup(_) => "basic_group",
+        _ => "other",
+    }
+}
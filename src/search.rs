@@ -0,0 +1,181 @@
+use std::fmt;
+
+use rusqlite::{Connection, OptionalExtension};
+use serde::Serialize;
+use tdlib::{
+    enums::MessageSender,
+    types::{Message, MessageSenderChat, MessageSenderUser},
+};
+
+use crate::{database::Database, error::AlterResult, render, utils};
+
+#[derive(Serialize)]
+pub struct SearchHit {
+    pub date: String,
+    pub chat_title: String,
+    pub sender_name: String,
+    pub snippet: String,
+}
+
+impl fmt::Display for SearchHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] {} - {}: {}",
+            self.date, self.chat_title, self.sender_name, self.snippet
+        )
+    }
+}
+
+pub fn index(conn: &Connection, message: &Message) -> AlterResult<()> {
+    unindex(conn, message.chat_id, message.id)?;
+    if let Some(text) = render::content_text(&message.content) {
+        conn.execute(
+            r#"INSERT INTO MESSAGES_FTS_KEYS (chat_id, message_id) VALUES (:chat_id, :message_id)"#,
+            rusqlite::named_params! {
+                ":chat_id": message.chat_id,
+                ":message_id": message.id,
+            },
+        )?;
+        conn.execute(
+            r#"INSERT INTO MESSAGES_FTS (rowid, text) VALUES (:rowid, :text)"#,
+            rusqlite::named_params! {
+                ":rowid": conn.last_insert_rowid(),
+                ":text": text,
+            },
+        )?;
+    }
+    Ok(())
+}
+
+pub fn unindex(conn: &Connection, chat_id: i64, message_id: i64) -> AlterResult<()> {
+    let key = conn
+        .query_row(
+            r#"SELECT id FROM MESSAGES_FTS_KEYS WHERE chat_id = :chat_id AND message_id = :message_id"#,
+            rusqlite::named_params! {
+                ":chat_id": chat_id,
+                ":message_id": message_id,
+            },
+            |row| row.get::<_, i64>(0),
+        )
+        .optional()?;
+    if let Some(key) = key {
+        conn.execute(
+            r#"DELETE FROM MESSAGES_FTS WHERE rowid = ?1"#,
+            rusqlite::params![key],
+        )?;
+        conn.execute(
+            r#"DELETE FROM MESSAGES_FTS_KEYS WHERE id = ?1"#,
+            rusqlite::params![key],
+        )?;
+    }
+    Ok(())
+}
+
+/// Searches the stored messages containing every word of the query, best matches first.
+pub async fn search(db: &Database, query: &str, limit: usize) -> AlterResult<Vec<SearchHit>> {
+    let Some(query) = match_query(query) else {
+        return Ok(Vec::new());
+    };
+    let rows = db
+        .execute(move |conn| {
+            let mut statement = conn.prepare(
+                r#"SELECT
+                MESSAGES_FTS_KEYS.chat_id,
+                MESSAGES.sender_id,
+                MESSAGES.date,
+                snippet(MESSAGES_FTS, 0, '[', ']', '…', 12)
+            FROM MESSAGES_FTS
+            JOIN MESSAGES_FTS_KEYS ON MESSAGES_FTS_KEYS.id = MESSAGES_FTS.rowid
+            JOIN MESSAGES ON MESSAGES.chat_id = MESSAGES_FTS_KEYS.chat_id
+                AND MESSAGES.id = MESSAGES_FTS_KEYS.message_id
+            WHERE MESSAGES_FTS MATCH :query
+            ORDER BY rank
+            LIMIT :limit"#,
+            )?;
+            let rows = statement
+                .query_map(
+                    rusqlite::named_params! {
+                        ":query": query,
+                        ":limit": limit as i64,
+                    },
+                    |row| {
+                        Ok((
+                            row.get::<_, i64>(0)?,
+                            row.get::<_, String>(1)?,
+                            row.get::<_, i64>(2)?,
+                            row.get::<_, String>(3)?,
+                        ))
+                    },
+                )?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(rows)
+        })
+        .await?;
+
+    let mut hits = Vec::with_capacity(rows.len());
+    for (chat_id, sender_id, date, snippet) in rows {
+        let sender_name = match serde_json::from_str(&sender_id)? {
+            MessageSender::User(MessageSenderUser { user_id }) => {
+                utils::user_display_name(db, user_id).await
+            }
+            MessageSender::Chat(MessageSenderChat { chat_id }) => {
+                utils::chat_display_name(db, chat_id).await
+            }
+        };
+        hits.push(SearchHit {
+            date: utils::format_time(date),
+            chat_title: utils::chat_display_name(db, chat_id).await,
+            sender_name,
+            snippet,
+        });
+    }
+    Ok(hits)
+}
+
+// Quotes each word, so that no input is taken for the FTS5 query syntax
+fn match_query(text: &str) -> Option<String> {
+    let words = text
+        .split_whitespace()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>();
+    (!words.is_empty()).then(|| words.join(" "))
+}
+
+pub async fn run(db: &Database, query: &str, limit: usize) -> AlterResult<()> {
+    let hits = search(db, query, limit).await?;
+    for hit in &hits {
+        println!("{hit}");
+    }
+    println!("{} matching message(s)", hits.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_any_input_literally() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            r#"CREATE VIRTUAL TABLE MESSAGES_FTS USING fts5(text);
+            INSERT INTO MESSAGES_FTS (text) VALUES ('what''s up? NOT much (really)');"#,
+        )
+        .unwrap();
+        let count = |text: &str| -> i64 {
+            conn.query_row(
+                r#"SELECT COUNT(*) FROM MESSAGES_FTS WHERE MESSAGES_FTS MATCH ?1"#,
+                rusqlite::params![match_query(text).unwrap()],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        assert_eq!(count("what's up?"), 1);
+        assert_eq!(count("NOT much"), 1);
+        assert_eq!(count("(really"), 1);
+        assert_eq!(count("\"much"), 1);
+        assert_eq!(count("much AND nothing"), 0);
+        assert_eq!(match_query("  "), None);
+    }
+}
@@ -0,0 +1,321 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use log::{error, info};
+use tdlib::types::Message;
+use tokio::sync::watch;
+
+use crate::{
+    config::Config,
+    database::Database,
+    error::AlterResult,
+    models::{
+        decode_rows,
+        message_provenance::NOT_GENERATED,
+        message_wrapper::MessageWrapper,
+        style_profile::{StyleProfile, StyleStats, OVERALL_CHAT_ID},
+        AutoRequestable, RowError,
+    },
+    utils,
+};
+
+// Below this, a chat falls back to the overall profile
+const MIN_MESSAGES: usize = 20;
+const COMMON_PHRASES: usize = 5;
+const MIN_PHRASE_COUNT: usize = 3;
+
+const LANGUAGES: &[(&str, &[&str])] = &[
+    (
+        "English",
+        &[
+            "the", "and", "you", "is", "are", "it", "to", "of", "what", "that", "with", "for",
+            "have", "not", "this",
+        ],
+    ),
+    (
+        "French",
+        &[
+            "le", "la", "les", "et", "est", "je", "tu", "pas", "que", "de", "un", "une", "pour",
+            "c'est", "ça",
+        ],
+    ),
+    (
+        "Spanish",
+        &[
+            "el", "los", "las", "y", "es", "yo", "que", "de", "una", "por", "para", "pero", "muy",
+            "está", "qué",
+        ],
+    ),
+    (
+        "German",
+        &[
+            "der", "die", "das", "und", "ist", "ich", "du", "nicht", "ein", "eine", "mit", "auch",
+            "was", "wie", "zu",
+        ],
+    ),
+];
+
+/// Recomputes the style profiles at the configured interval.
+pub async fn run(db: Database, config_rx: watch::Receiver<Arc<Config>>) {
+    loop {
+        if let Err(e) = refresh(&db).await {
+            error!("{e:#?}");
+        }
+        let refresh_minutes = config_rx.borrow().style.refresh_minutes;
+        tokio::time::sleep(Duration::from_secs(refresh_minutes * 60)).await;
+    }
+}
+
+async fn refresh(db: &Database) -> AlterResult<()> {
+    let rows = db
+        .execute(|conn| {
+            decode_rows::<MessageWrapper>(
+                MessageWrapper::TABLE,
+                conn.prepare(&format!(
+                    r#"SELECT rowid AS row_id, * FROM MESSAGES WHERE is_outgoing = 1 AND {NOT_GENERATED}"#
+                ))?
+                .query(rusqlite::params![])?,
+            )
+        })
+        .await?;
+    let updated_at = utils::unix_time();
+    let (profiles, message_count) =
+        tokio::task::spawn_blocking(move || profiles(rows, updated_at)).await?;
+    info!(
+        "Computed {} style profiles from {message_count} messages",
+        profiles.len()
+    );
+    db.write(move |conn| {
+        for profile in &profiles {
+            profile.upsert(conn)?;
+        }
+        // The chats which no longer have enough of my messages would keep an outdated style
+        let pruned = StyleProfile::prune(conn, updated_at)?;
+        if pruned > 0 {
+            info!("Deleted {pruned} outdated style profiles");
+        }
+        Ok(())
+    })
+}
+
+// The profiles of the chats with enough of my messages, and the number of messages read
+fn profiles(
+    rows: Vec<Result<MessageWrapper, RowError>>,
+    updated_at: i64,
+) -> (Vec<StyleProfile>, usize) {
+    let messages = rows
+        .into_iter()
+        .filter_map(|message| message.map_err(|e| error!("{e:#?}")).ok())
+        .map(<MessageWrapper as Into<Message>>::into)
+        .filter_map(|message| Some((message.chat_id, utils::message_text(&message)?)))
+        .collect::<Vec<_>>();
+
+    let mut chats: HashMap<i64, Vec<&str>> = HashMap::new();
+    for (chat_id, text) in &messages {
+        chats.entry(*chat_id).or_default().push(text);
+        chats.entry(OVERALL_CHAT_ID).or_default().push(text);
+    }
+
+    let profiles = chats
+        .into_iter()
+        .filter(|(_, texts)| texts.len() >= MIN_MESSAGES)
+        .map(|(chat_id, texts)| StyleProfile {
+            chat_id,
+            stats: analyse(&texts),
+            updated_at,
+        })
+        .collect();
+    (profiles, messages.len())
+}
+
+fn analyse(texts: &[&str]) -> StyleStats {
+    let count = texts.len();
+    let ratio = |predicate: &dyn Fn(&str) -> bool| {
+        texts.iter().filter(|text| predicate(text)).count() as f64 / count as f64
+    };
+
+    let mut phrases: HashMap<String, usize> = HashMap::new();
+    let mut languages: HashMap<&str, usize> = HashMap::new();
+    for text in texts {
+        let words = words(text);
+        for phrase in words.windows(2) {
+            *phrases.entry(phrase.join(" ")).or_default() += 1;
+        }
+        if let Some(language) = language(&words) {
+            *languages.entry(language).or_default() += 1;
+        }
+    }
+    let mut phrases = phrases
+        .into_iter()
+        .filter(|(_, count)| *count >= MIN_PHRASE_COUNT)
+        .collect::<Vec<_>>();
+    phrases.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let detected = languages.values().sum::<usize>().max(1) as f64;
+    let mut languages = languages
+        .into_iter()
+        .map(|(language, count)| (language.to_owned(), count as f64 / detected))
+        .collect::<Vec<_>>();
+    languages.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    StyleStats {
+        message_count: count,
+        average_length: texts.iter().map(|text| text.chars().count()).sum::<usize>() as f64
+            / count as f64,
+        emojis_per_message: texts
+            .iter()
+            .map(|text| text.chars().filter(|c| is_emoji(*c)).count())
+            .sum::<usize>() as f64
+            / count as f64,
+        capitalised: ratio(&|text| text.chars().next().is_some_and(char::is_uppercase)),
+        ending_with_period: ratio(&|text| {
+            let text = text.trim_end();
+            text.ends_with('.') && !text.ends_with("..")
+        }),
+        with_exclamation: ratio(&|text| text.contains('!')),
+        with_question: ratio(&|text| text.contains('?')),
+        with_ellipsis: ratio(&|text| text.contains("...") || text.contains('…')),
+        common_phrases: phrases
+            .into_iter()
+            .take(COMMON_PHRASES)
+            .map(|(phrase, _)| phrase)
+            .collect(),
+        languages,
+    }
+}
+
+/// Renders the profile of a chat, or the overall one, as a section of a system prompt.
+pub async fn prompt_section(db: &Database, chat_id: i64) -> AlterResult<Option<String>> {
+    let profile = match db.load::<StyleProfile>(chat_id).await? {
+        Some(profile) => profile,
+        None => match db.load::<StyleProfile>(OVERALL_CHAT_ID).await? {
+            Some(profile) => profile,
+            None => return Ok(None),
+        },
+    };
+    let stats = profile.stats;
+    let percent = |ratio: f64| (ratio * 100.).round();
+    let mut section = vec![
+        "Write the way I do. My writing style:".to_owned(),
+        format!(
+            "- Messages are {:.0} characters long on average",
+            stats.average_length
+        ),
+        format!("- {:.1} emojis per message", stats.emojis_per_message),
+        format!(
+            "- {}% of messages start with a capital letter and {}% end with a period",
+            percent(stats.capitalised),
+            percent(stats.ending_with_period)
+        ),
+        format!(
+            "- {}% of messages contain '!', {}% contain '?' and {}% contain '...'",
+            percent(stats.with_exclamation),
+            percent(stats.with_question),
+            percent(stats.with_ellipsis)
+        ),
+    ];
+    if !stats.common_phrases.is_empty() {
+        section.push(format!(
+            "- Frequent phrases: {}",
+            stats
+                .common_phrases
+                .iter()
+                .map(|phrase| format!("\"{phrase}\""))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    if !stats.languages.is_empty() {
+        section.push(format!(
+            "- Languages: {}",
+            stats
+                .languages
+                .iter()
+                .map(|(language, ratio)| format!("{language} {}%", percent(*ratio)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    Ok(Some(section.join("\n")))
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+// The language with the most stop words, if any
+fn language(words: &[String]) -> Option<&'static str> {
+    LANGUAGES
+        .iter()
+        .map(|(language, stop_words)| {
+            let count = words
+                .iter()
+                .filter(|word| stop_words.contains(&word.as_str()))
+                .count();
+            (*language, count)
+        })
+        .filter(|(_, count)| *count > 0)
+        .max_by_key(|(_, count)| *count)
+        .map(|(language, _)| language)
+}
+
+fn is_emoji(c: char) -> bool {
+    matches!(c as u32, 0x1F300..=0x1FAFF | 0x2600..=0x27BF | 0x1F000..=0x1F2FF)
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+
+    use super::*;
+    use crate::migrations;
+
+    #[test]
+    fn analyses_my_writing() {
+        let stats = analyse(&[
+            "Ça va ?",
+            "ça va bien.",
+            "Ok ça va 👍👍",
+            "Je suis là...",
+            "What a day!",
+        ]);
+        assert_eq!(stats.message_count, 5);
+        assert_eq!(stats.average_length, 10.6);
+        assert_eq!(stats.emojis_per_message, 0.4);
+        assert_eq!(stats.capitalised, 0.8);
+        // An ellipsis is no period
+        assert_eq!(stats.ending_with_period, 0.2);
+        assert_eq!(stats.with_exclamation, 0.2);
+        assert_eq!(stats.with_question, 0.2);
+        assert_eq!(stats.with_ellipsis, 0.2);
+        assert_eq!(stats.common_phrases, vec!["ça va".to_owned()]);
+        assert_eq!(
+            stats.languages,
+            vec![("French".to_owned(), 0.8), ("English".to_owned(), 0.2)]
+        );
+    }
+
+    #[test]
+    fn prunes_the_profiles_of_earlier_runs() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrations::run(&mut conn).unwrap();
+        for (chat_id, updated_at) in [(OVERALL_CHAT_ID, 200), (1, 200), (2, 100)] {
+            StyleProfile {
+                chat_id,
+                stats: StyleStats::default(),
+                updated_at,
+            }
+            .upsert(&conn)
+            .unwrap();
+        }
+
+        assert_eq!(StyleProfile::prune(&conn, 200).unwrap(), 1);
+        let kept = StyleProfile::select_all(&conn)
+            .unwrap()
+            .into_iter()
+            .map(|profile| profile.chat_id)
+            .collect::<Vec<_>>();
+        assert_eq!(kept, vec![OVERALL_CHAT_ID, 1]);
+    }
+}
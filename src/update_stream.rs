@@ -1,23 +1,122 @@
 use std::{
-    pin::Pin,
-    task::{Context, Poll},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
-use futures::Stream;
+use log::{debug, error, info, warn};
 use tdlib::enums::Update;
+use tokio::sync::mpsc::{self, error::TrySendError};
 
-pub struct UpdateStream;
+use crate::{error::AlterResult, metrics};
 
-impl Stream for UpdateStream {
-    type Item = (Update, i32);
+// Waiting longer than this for room in the channel is worth a warning
+const SLOW_CONSUMER: Duration = Duration::from_secs(1);
 
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        match tdlib::receive() {
-            Some((update, client_id)) => Poll::Ready(Some((update, client_id))),
-            None => {
-                cx.waker().wake_by_ref();
-                Poll::Pending
+#[derive(Debug, Default)]
+pub struct UpdateStreamMetrics {
+    pub received: AtomicU64,
+    /// Updates which had to wait for room in the channel
+    pub blocked: AtomicU64,
+    pub blocked_ms: AtomicU64,
+    /// Updates waiting in the channel, as of the last one received
+    pub depth: AtomicU64,
+    pub max_depth: AtomicU64,
+}
+
+/// Receives the updates of every TDLib client on a dedicated thread, as `tdlib::receive`
+/// blocks, and feeds them to a bounded channel. When the channel is full, the thread waits,
+/// leaving the updates queued in TDLib.
+pub struct UpdateStream {
+    running: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+    metrics: Arc<UpdateStreamMetrics>,
+}
+
+impl UpdateStream {
+    pub fn spawn(capacity: usize) -> AlterResult<(Self, mpsc::Receiver<(Update, i32)>)> {
+        let (update_tx, update_rx) = mpsc::channel(capacity);
+        let running = Arc::new(AtomicBool::new(true));
+        let metrics = Arc::new(UpdateStreamMetrics::default());
+        metrics::register_update_stream(metrics.clone());
+        let thread = thread::Builder::new().name("tdlib-receive".into()).spawn({
+            let running = running.clone();
+            let metrics = metrics.clone();
+            move || receive(update_tx, &running, &metrics)
+        })?;
+        Ok((
+            Self {
+                running,
+                thread: Some(thread),
+                metrics,
+            },
+            update_rx,
+        ))
+    }
+
+    /// Stops the thread, which closes the channel. Waits for at most one TDLib receive
+    /// timeout.
+    pub async fn stop(mut self) -> AlterResult<()> {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            if tokio::task::spawn_blocking(move || thread.join())
+                .await?
+                .is_err()
+            {
+                error!("The TDLib receive thread panicked");
+            }
+        }
+        info!(
+            "Received {} updates, {} of which waited {} ms in total for room in the queue, \
+            which held up to {} updates",
+            self.metrics.received.load(Ordering::Relaxed),
+            self.metrics.blocked.load(Ordering::Relaxed),
+            self.metrics.blocked_ms.load(Ordering::Relaxed),
+            self.metrics.max_depth.load(Ordering::Relaxed),
+        );
+        Ok(())
+    }
+}
+
+fn receive(
+    update_tx: mpsc::Sender<(Update, i32)>,
+    running: &AtomicBool,
+    metrics: &UpdateStreamMetrics,
+) {
+    debug!("Start receiving updates");
+    while running.load(Ordering::Relaxed) {
+        // Returns `None` after a timeout without updates
+        let Some(update) = tdlib::receive() else {
+            continue;
+        };
+        metrics.received.fetch_add(1, Ordering::Relaxed);
+        let sent = match update_tx.try_send(update) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(update)) => {
+                let start = Instant::now();
+                let sent = update_tx.blocking_send(update).map_err(|_| ());
+                let blocked = start.elapsed();
+                metrics.blocked.fetch_add(1, Ordering::Relaxed);
+                metrics
+                    .blocked_ms
+                    .fetch_add(blocked.as_millis() as u64, Ordering::Relaxed);
+                if blocked > SLOW_CONSUMER {
+                    warn!("Updates are consumed too slowly, waited {blocked:?} for room");
+                }
+                sent
             }
+            Err(TrySendError::Closed(_)) => Err(()),
+        };
+        if sent.is_err() {
+            debug!("Update channel closed");
+            break;
         }
+        let depth = (update_tx.max_capacity() - update_tx.capacity()) as u64;
+        metrics.depth.store(depth, Ordering::Relaxed);
+        metrics.max_depth.fetch_max(depth, Ordering::Relaxed);
     }
+    debug!("Stop receiving updates");
 }
@@ -1,7 +1,4 @@
-use std::{
-    sync::{Arc, Mutex},
-    time,
-};
+use std::time;
 
 use log::debug;
 use rand::Rng;
@@ -17,35 +14,88 @@ pub fn rand_between(min: u64, max: u64) -> u64 {
     (rand::thread_rng().gen::<f64>() * (max - min) as f64) as u64 + min
 }
 
+pub fn unix_time() -> i64 {
+    time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64)
+}
+
+/// Stable 64 bits FNV-1a hash, as hexadecimal.
+pub fn hash(text: &str) -> String {
+    let hash = text.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{hash:016x}")
+}
+
+/// Parses a `YYYY-MM-DD` date into the unix time of its UTC midnight.
+pub fn parse_date(date: &str) -> Result<i64, String> {
+    let mut parts = date.splitn(3, '-').map(str::parse::<i64>);
+    let (Some(Ok(year)), Some(Ok(month)), Some(Ok(day))) =
+        (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(format!("expected a YYYY-MM-DD date, got '{date}'"));
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(format!("'{date}' is not a valid date"));
+    }
+    // http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    Ok((era * 146_097 + day_of_era - 719_468) * 86_400)
+}
+
+/// Formats a unix time as a `YYYY-MM-DD HH:MM` UTC date and time.
+pub fn format_time(unix_time: i64) -> String {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = unix_time.div_euclid(86_400) + 719_468;
+    let seconds = unix_time.rem_euclid(86_400);
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}",
+        seconds / 3600,
+        seconds % 3600 / 60
+    )
+}
+
 pub async fn sleep_ms(waiting_time: u64) {
     debug!("Waiting for {waiting_time} ms");
     tokio::time::sleep(time::Duration::from_millis(waiting_time)).await;
 }
 
-pub fn user_display_name(db: Arc<Mutex<Database>>, user_id: i64) -> String {
-    db.lock()
-        .unwrap()
-        .load::<UserWrapper>(user_id)
-        .and_then(|user| {
-            Ok(user.map(|user| {
-                let user = <UserWrapper as Into<tdlib::types::User>>::into(user);
-                format!("{} {}", user.first_name, user.last_name)
-                    .trim()
-                    .into()
-            }))
-        })
+pub async fn user_display_name(db: &Database, user_id: i64) -> String {
+    db.load::<UserWrapper>(user_id)
+        .await
         .unwrap_or_default()
+        .map(|user| {
+            let user = <UserWrapper as Into<tdlib::types::User>>::into(user);
+            format!("{} {}", user.first_name, user.last_name)
+                .trim()
+                .into()
+        })
         .unwrap_or_else(|| user_id.to_string())
 }
 
-pub fn chat_display_name(db: Arc<Mutex<Database>>, chat_id: i64) -> String {
-    db.lock()
-        .unwrap()
-        .load::<ChatWrapper>(chat_id)
-        .and_then(|chat| {
-            Ok(chat.map(|chat| <ChatWrapper as Into<tdlib::types::Chat>>::into(chat).title))
-        })
+pub async fn chat_display_name(db: &Database, chat_id: i64) -> String {
+    db.load::<ChatWrapper>(chat_id)
+        .await
         .unwrap_or_default()
+        .map(|chat| <ChatWrapper as Into<tdlib::types::Chat>>::into(chat).title)
         .unwrap_or_else(|| chat_id.to_string())
 }
 